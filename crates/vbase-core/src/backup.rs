@@ -0,0 +1,281 @@
+//! Backups of databases.
+//!
+//! A [`BackupEngine`] keeps backups in a directory of any [`Env`]. Each
+//! backup is described by a `backup-{id}` file, which lists the files of the
+//! database at the time of the backup. The content of these files is stored
+//! under the `files` directory, named by their sizes and checksums, so that
+//! files that have not changed since previous backups are only stored once.
+//! CRC32 checksums can collide, so a stored file is only reused if its bytes
+//! are the same, and a different file with the same name, size and checksum
+//! is stored as another copy.
+
+use std::collections::HashSet;
+use std::io::ErrorKind;
+
+use log::info;
+use prost::Message;
+use vbase_env::SequentialFileWriter as _;
+use vbase_env::boxed::Dir;
+use vbase_env::boxed::Env;
+use vbase_util::codec::Decode;
+use vbase_util::codec::Encode;
+use vbase_util::crc32::checksum;
+use vbase_util::span;
+
+use crate::Core;
+use crate::Error;
+use crate::Result;
+use crate::error::Corrupted;
+
+/// Creates, lists and restores backups in a directory.
+pub struct BackupEngine {
+    dir: Dir,
+    files: Dir,
+}
+
+impl BackupEngine {
+    const FILES: &str = "files";
+    const MANIFEST: &str = "MANIFEST";
+
+    /// Creates a backup engine in `dir`.
+    ///
+    /// Existing backups in `dir` are kept.
+    pub fn create(dir: Dir) -> Result<Self> {
+        let files = dir.create_dir(Self::FILES)?;
+        Ok(Self { dir, files })
+    }
+
+    /// Creates a backup of `core` and returns its id.
+    ///
+    /// Only files that are not stored by previous backups are copied. Files
+    /// that may have been stored are read back and compared before they are
+    /// skipped.
+    pub fn create_backup(&self, core: &Core) -> Result<u64> {
+        let id = self.list_ids()?.last().map_or(1, |id| id + 1);
+        span!("backup", id);
+        let mut stored = self.files.list()?.into_iter().collect::<HashSet<_>>();
+        let mut desc = BackupDesc::default();
+        core.backup_files(&mut |name, data| {
+            let mut file = FileDesc {
+                name: name.into(),
+                size: data.len() as u64,
+                checksum: checksum(data),
+                copy: 0,
+            };
+            self.store_file(&mut stored, &mut file, data)?;
+            desc.files.push(file);
+            Ok(())
+        })?;
+        info!("create backup {id} with {} files", desc.files.len());
+        write_file(&self.dir, &backup_name(id), &desc.encode_with_checksum())?;
+        Ok(id)
+    }
+
+    /// Returns all backups in id order.
+    pub fn list_backups(&self) -> Result<Vec<BackupInfo>> {
+        self.list_ids()?
+            .into_iter()
+            .map(|id| {
+                let desc = self.read_backup(id)?;
+                Ok(BackupInfo {
+                    id,
+                    num_files: desc.files.len(),
+                    size: desc.files.iter().map(|f| f.size).sum(),
+                })
+            })
+            .collect()
+    }
+
+    /// Restores backup `id` to `path` in `env`.
+    ///
+    /// The database is restored with the default layout, where journals and
+    /// engines are all in `path`. The manifest is written last, so that an
+    /// interrupted restore can not be opened with
+    /// [`crate::options::Builder::error_if_not_exist`].
+    ///
+    /// # Errors
+    ///
+    /// - Returns [`Error::NotExist`] if the backup does not exist.
+    /// - Returns [`Error::Exists`] if `path` is not empty.
+    /// - Returns [`Error::Corrupted`] if any stored file is corrupted.
+    pub fn restore_to(&self, id: u64, env: &Env, path: &str) -> Result<()> {
+        span!("restore", id, path);
+        let desc = self.read_backup(id)?;
+        let dir = env.create_dir(path)?;
+        if !dir.list()?.is_empty() {
+            return Err(Error::Exists(format!("files in {path}")));
+        }
+        info!("restore backup {id} to {path}");
+        let (manifest, files): (Vec<_>, Vec<_>) =
+            desc.files.iter().partition(|f| f.name == Self::MANIFEST);
+        for file in files.into_iter().chain(manifest) {
+            let data = self.read_file(file)?;
+            match file.name.rsplit_once('/') {
+                Some((parent, name)) => write_file(&dir.create_dir(parent)?, name, &data)?,
+                None => write_file(&dir, &file.name, &data)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BackupEngine {
+    /// Stores `data` unless the same bytes are stored for `file`.
+    ///
+    /// Stored files with the same name, size and checksum are compared one by
+    /// one, and `data` is stored as the next copy if none of them matches.
+    fn store_file(
+        &self,
+        stored: &mut HashSet<String>,
+        file: &mut FileDesc,
+        data: &[u8],
+    ) -> Result<()> {
+        loop {
+            let name = file.stored_name();
+            if !stored.contains(&name) {
+                write_file(&self.files, &name, data)?;
+                stored.insert(name);
+                return Ok(());
+            }
+            if self.files.read_file(&name)? == data {
+                return Ok(());
+            }
+            file.copy += 1;
+        }
+    }
+
+    /// Returns the ids of all backups in order.
+    fn list_ids(&self) -> Result<Vec<u64>> {
+        let mut ids = self
+            .dir
+            .list()?
+            .iter()
+            .filter_map(|name| name.strip_prefix("backup-")?.parse().ok())
+            .collect::<Vec<u64>>();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn read_backup(&self, id: u64) -> Result<BackupDesc> {
+        let name = backup_name(id);
+        match self.dir.read_file(&name) {
+            Ok(data) => BackupDesc::decode_with_checksum(&data).or_else(|e| name.corrupted(e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Err(Error::NotExist(format!("backup {id}")))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reads a stored file and checks that it matches `file`.
+    fn read_file(&self, file: &FileDesc) -> Result<Vec<u8>> {
+        let name = file.stored_name();
+        let data = self.files.read_file(&name)?;
+        if data.len() as u64 != file.size {
+            return name.corrupted(format!(
+                "size mismatch (expected {}, got {})",
+                file.size,
+                data.len()
+            ));
+        }
+        let checksum = checksum(&data);
+        if checksum != file.checksum {
+            return name.corrupted(format!(
+                "checksum mismatch (expected {}, got {checksum})",
+                file.checksum
+            ));
+        }
+        Ok(data)
+    }
+}
+
+/// Information about a backup.
+#[derive(Clone, Debug)]
+pub struct BackupInfo {
+    id: u64,
+    num_files: usize,
+    size: u64,
+}
+
+impl BackupInfo {
+    /// Returns the id of the backup.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the number of files in the backup.
+    pub fn num_files(&self) -> usize {
+        self.num_files
+    }
+
+    /// Returns the total size of files in the backup, including files shared
+    /// with other backups.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+#[derive(Clone, Message)]
+struct BackupDesc {
+    #[prost(tag = "1", repeated, message)]
+    files: Vec<FileDesc>,
+}
+
+impl BackupDesc {
+    fn encode_with_checksum(&self) -> Vec<u8> {
+        let mut buf = self.encode_to_vec();
+        checksum(&buf).encode_to(&mut buf);
+        buf
+    }
+
+    fn decode_with_checksum(buf: &[u8]) -> Result<Self, String> {
+        let (message, mut crc) = buf
+            .split_at_checked(buf.len().wrapping_sub(4))
+            .ok_or_else(|| format!("invalid size {}", buf.len()))?;
+        let checksum = checksum(message);
+        let expected = u32::decode_from(&mut crc);
+        if checksum != expected {
+            return Err(format!(
+                "checksum mismatch (expected {expected}, got {checksum})"
+            ));
+        }
+        Self::decode(message).map_err(|e| format!("{e}"))
+    }
+}
+
+#[derive(Clone, Message)]
+struct FileDesc {
+    /// The path of the file relative to the database.
+    #[prost(tag = "1", string)]
+    name: String,
+    #[prost(tag = "2", uint64)]
+    size: u64,
+    #[prost(tag = "3", uint32)]
+    checksum: u32,
+    /// Tells apart different files with the same name, size and checksum.
+    #[prost(tag = "4", uint32)]
+    copy: u32,
+}
+
+impl FileDesc {
+    /// Returns the name of the file in the `files` directory.
+    fn stored_name(&self) -> String {
+        let name = self.name.replace('/', "-");
+        match self.copy {
+            0 => format!("{name}.{}.{:08x}", self.size, self.checksum),
+            copy => format!("{name}.{}.{:08x}.{copy}", self.size, self.checksum),
+        }
+    }
+}
+
+fn backup_name(id: u64) -> String {
+    format!("backup-{id}")
+}
+
+/// Writes a file atomically.
+fn write_file(dir: &Dir, name: &str, data: &[u8]) -> Result<()> {
+    let mut file = dir.create_temp_file(name)?;
+    file.write_exact(data)?;
+    file.persist(name)?;
+    Ok(())
+}
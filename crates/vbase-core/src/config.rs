@@ -0,0 +1,500 @@
+//! A TOML format of options.
+//!
+//! Each option is a key with the name of its setter, and a `_ms` suffix for
+//! durations in milliseconds. Values are integers, booleans or strings, and
+//! sizes can also be `"unlimited"`. Engine directories are in the
+//! `engine_dirs` table, keyed by engine names.
+//!
+//! This implements the parts of TOML 1.0 that options can use: bare, quoted
+//! and dotted keys, tables and inline tables, all forms of strings and
+//! integers, and booleans. Floats, dates and arrays are rejected, since no
+//! option takes them.
+//!
+//! Options that can not be represented in text, such as the environment and
+//! the clock, are left out.
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::options::Options;
+
+/// Parses `text` into `options`.
+///
+/// Returns an error message with the line number if `text` is invalid.
+pub(crate) fn parse(options: &mut Options, text: &str) -> Result<(), String> {
+    let mut parser = Parser { text, pos: 0 };
+    let mut entries = Vec::new();
+    parser
+        .parse(&mut entries)
+        .map_err(|e| format!("line {}: {e}", parser.line()))?;
+    let mut keys = HashSet::new();
+    for (line, key, value) in entries {
+        let result = if keys.insert(key.clone()) {
+            set(options, &key, value)
+        } else {
+            Err(format!("duplicate key `{}`", key.join(".")))
+        };
+        result.map_err(|e| format!("line {line}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Formats `options` in TOML.
+pub(crate) fn format(options: &Options) -> String {
+    let mut text = String::new();
+    let mut put = |key: &str, value: String| writeln!(text, "{key} = {value}").unwrap();
+    if let Some(path) = &options.paths.journal {
+        put("journal_dir", quote(path));
+    }
+    put("journal_file_size", options.journal_file_size.to_string());
+    put(
+        "journal_write_queue",
+        options.journal_write_queue.to_string(),
+    );
+    put("max_batch_size", size(options.max_batch_size));
+    put("batch_pool_size", options.batch_pool_size.to_string());
+    put("background_publish", options.background_publish.to_string());
+    put("steal_stale_lock", options.steal_stale_lock.to_string());
+    if let Some(retention) = options.trash_retention {
+        put("trash_retention_ms", retention.as_millis().to_string());
+    }
+    put("trash_purge_rate", options.trash_purge_rate.to_string());
+    put("write_slowdown_size", size(options.write_slowdown_size));
+    put(
+        "write_slowdown_delay_ms",
+        options.write_slowdown_delay.as_millis().to_string(),
+    );
+    put("write_stop_size", size(options.write_stop_size));
+    put(
+        "high_priority_threads",
+        options.high_priority_threads.to_string(),
+    );
+    put(
+        "low_priority_threads",
+        options.low_priority_threads.to_string(),
+    );
+    put("info_log_size", options.info_log_size.to_string());
+    put("info_log_files", options.info_log_files.to_string());
+    if !options.paths.engines.is_empty() {
+        text.push_str("\n[engine_dirs]\n");
+        for (name, path) in options.paths.engines.iter().collect::<BTreeMap<_, _>>() {
+            writeln!(text, "{} = {}", quote(name), quote(path)).unwrap();
+        }
+    }
+    text
+}
+
+fn set(options: &mut Options, key: &[String], value: Value) -> Result<(), String> {
+    let key = key.iter().map(String::as_str).collect::<Vec<_>>();
+    match key[..] {
+        ["journal_dir"] => options.paths.journal = Some(value.string()?),
+        ["journal_file_size"] => options.journal_file_size = value.size()?,
+        ["journal_write_queue"] => options.journal_write_queue = value.size()?,
+        ["max_batch_size"] => options.max_batch_size = value.size()?,
+        ["batch_pool_size"] => options.batch_pool_size = value.size()?,
+        ["background_publish"] => options.background_publish = value.bool()?,
+        ["steal_stale_lock"] => options.steal_stale_lock = value.bool()?,
+        ["trash_retention_ms"] => options.trash_retention = Some(value.duration()?),
+        ["trash_purge_rate"] => options.trash_purge_rate = value.int()?,
+        ["write_slowdown_size"] => options.write_slowdown_size = value.size()?,
+        ["write_slowdown_delay_ms"] => options.write_slowdown_delay = value.duration()?,
+        ["write_stop_size"] => options.write_stop_size = value.size()?,
+        ["high_priority_threads"] => options.high_priority_threads = value.size()?,
+        ["low_priority_threads"] => options.low_priority_threads = value.size()?,
+        ["info_log_size"] => options.info_log_size = value.size()?,
+        ["info_log_files"] => options.info_log_files = value.size()?,
+        ["engine_dirs", name] => {
+            options.paths.engines.insert(name.into(), value.string()?);
+        }
+        _ => return Err(format!("unknown option `{}`", key.join("."))),
+    }
+    Ok(())
+}
+
+enum Value {
+    Int(u64),
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    fn int(self) -> Result<u64, String> {
+        match self {
+            Self::Int(x) => Ok(x),
+            _ => Err("expected an integer".into()),
+        }
+    }
+
+    fn size(self) -> Result<usize, String> {
+        match self {
+            Self::Str(s) if s == "unlimited" => Ok(usize::MAX),
+            x => usize::try_from(x.int()?).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn duration(self) -> Result<Duration, String> {
+        self.int().map(Duration::from_millis)
+    }
+
+    fn bool(self) -> Result<bool, String> {
+        match self {
+            Self::Bool(x) => Ok(x),
+            _ => Err("expected a boolean".into()),
+        }
+    }
+
+    fn string(self) -> Result<String, String> {
+        match self {
+            Self::Str(x) => Ok(x),
+            _ => Err("expected a string".into()),
+        }
+    }
+}
+
+fn size(size: usize) -> String {
+    if size == usize::MAX {
+        quote("unlimited")
+    } else {
+        size.to_string()
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04X}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Tables that can be defined with headers.
+const TABLES: &[&str] = &["engine_dirs"];
+
+/// An entry with its line, full key and value.
+type Entry = (usize, Vec<String>, Value);
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// Returns the line of the current position.
+    fn line(&self) -> usize {
+        self.text[..self.pos].matches('\n').count() + 1
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let found = self.rest().starts_with(s);
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.bump() {
+            Some(x) if x == c => Ok(()),
+            Some(x) => Err(format!("expected `{c}` but found `{x}`")),
+            None => Err(format!("expected `{c}`")),
+        }
+    }
+
+    /// Takes the characters from the current position while `f` is true.
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.bump();
+        }
+        &self.text[start..self.pos]
+    }
+
+    /// Skips spaces and tabs.
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    /// Skips spaces and a comment, then expects the end of the line.
+    fn end_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        if self.eat("#") {
+            while !matches!(self.peek(), None | Some('\n' | '\r')) {
+                self.bump();
+            }
+        }
+        if self.peek().is_none() || self.eat("\n") || self.eat("\r\n") {
+            return Ok(());
+        }
+        Err("expected the end of the line".into())
+    }
+
+    fn parse(&mut self, entries: &mut Vec<Entry>) -> Result<(), String> {
+        let mut table = Vec::new();
+        let mut tables = HashSet::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None => return Ok(()),
+                Some('#' | '\n' | '\r') => {}
+                Some('[') => {
+                    self.bump();
+                    if self.peek() == Some('[') {
+                        return Err("arrays of tables are not supported".into());
+                    }
+                    self.skip_spaces();
+                    table = self.parse_key()?;
+                    self.expect(']')?;
+                    if !matches!(&table[..], [name] if TABLES.contains(&name.as_str())) {
+                        return Err(format!("unknown table `{}`", table.join(".")));
+                    }
+                    if !tables.insert(table.clone()) {
+                        return Err(format!("duplicate table `{}`", table.join(".")));
+                    }
+                }
+                Some(_) => {
+                    let line = self.line();
+                    let mut key = table.clone();
+                    key.extend(self.parse_key()?);
+                    self.expect('=')?;
+                    self.skip_spaces();
+                    self.parse_entry(line, key, entries)?;
+                }
+            }
+            self.end_line()?;
+        }
+    }
+
+    /// Parses a dotted key and the spaces after it.
+    fn parse_key(&mut self) -> Result<Vec<String>, String> {
+        let mut key = Vec::new();
+        loop {
+            let part = match self.peek() {
+                Some('"') => {
+                    self.bump();
+                    self.parse_basic_string()?
+                }
+                Some('\'') => {
+                    self.bump();
+                    self.parse_literal_string()?
+                }
+                _ => {
+                    let part = self.take_while(|c| c.is_ascii_alphanumeric() || "_-".contains(c));
+                    if part.is_empty() {
+                        return Err("expected a key".into());
+                    }
+                    part.to_owned()
+                }
+            };
+            key.push(part);
+            self.skip_spaces();
+            if !self.eat(".") {
+                return Ok(key);
+            }
+            self.skip_spaces();
+        }
+    }
+
+    /// Parses a value for `key`, flattening inline tables into entries.
+    fn parse_entry(
+        &mut self,
+        line: usize,
+        key: Vec<String>,
+        entries: &mut Vec<Entry>,
+    ) -> Result<(), String> {
+        if self.eat("{") {
+            self.skip_spaces();
+            if self.eat("}") {
+                return Ok(());
+            }
+            loop {
+                let mut inner = key.clone();
+                inner.extend(self.parse_key()?);
+                self.expect('=')?;
+                self.skip_spaces();
+                self.parse_entry(line, inner, entries)?;
+                self.skip_spaces();
+                if self.eat("}") {
+                    return Ok(());
+                }
+                self.expect(',')?;
+                self.skip_spaces();
+            }
+        }
+        let value = self.parse_value()?;
+        entries.push((line, key, value));
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        if self.eat("\"\"\"") {
+            return self.parse_multiline_string('"').map(Value::Str);
+        }
+        if self.eat("'''") {
+            return self.parse_multiline_string('\'').map(Value::Str);
+        }
+        if self.eat("\"") {
+            return self.parse_basic_string().map(Value::Str);
+        }
+        if self.eat("'") {
+            return self.parse_literal_string().map(Value::Str);
+        }
+        if self.peek() == Some('[') {
+            return Err("arrays are not supported".into());
+        }
+        let token = self.take_while(|c| c.is_ascii_alphanumeric() || "_+-.:".contains(c));
+        match token {
+            "" => Err("expected a value".into()),
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            token => parse_int(token).map(Value::Int),
+        }
+    }
+
+    /// Parses a basic string without the opening quote.
+    fn parse_basic_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.parse_escape()?),
+                Some('\n' | '\r') | None => return Err("unclosed string".into()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Parses a literal string without the opening quote.
+    fn parse_literal_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(s),
+                Some('\n' | '\r') | None => return Err("unclosed string".into()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Parses a multi-line string quoted with `quote` without the opening
+    /// quotes.
+    fn parse_multiline_string(&mut self, quote: char) -> Result<String, String> {
+        let delimiter = quote.to_string().repeat(3);
+        // A newline right after the opening quotes is trimmed.
+        if !self.eat("\n") {
+            self.eat("\r\n");
+        }
+        let mut s = String::new();
+        loop {
+            if self.eat(&delimiter) {
+                // Up to two more quotes belong to the string.
+                for _ in 0..2 {
+                    if self.peek() == Some(quote) {
+                        s.push(quote);
+                        self.bump();
+                    }
+                }
+                return Ok(s);
+            }
+            match self.bump() {
+                None => return Err("unclosed string".into()),
+                Some('\\') if quote == '"' => {
+                    // A backslash at the end of a line trims the whitespace
+                    // up to the next non-whitespace character.
+                    let rest = self.rest().trim_start_matches([' ', '\t']);
+                    if rest.starts_with('\n') || rest.starts_with("\r\n") {
+                        let trimmed = rest.trim_start_matches([' ', '\t', '\n', '\r']);
+                        self.pos = self.text.len() - trimmed.len();
+                    } else {
+                        s.push(self.parse_escape()?);
+                    }
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Parses an escape sequence without the backslash.
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let len = match self.bump() {
+            Some('b') => return Ok('\u{8}'),
+            Some('t') => return Ok('\t'),
+            Some('n') => return Ok('\n'),
+            Some('f') => return Ok('\u{C}'),
+            Some('r') => return Ok('\r'),
+            Some('"') => return Ok('"'),
+            Some('\\') => return Ok('\\'),
+            Some('u') => 4,
+            Some('U') => 8,
+            _ => return Err("invalid escape".into()),
+        };
+        let hex = self.rest().get(..len).unwrap_or_default();
+        let c = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == len && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(char::from_u32)
+            .ok_or("invalid escape")?;
+        self.pos += len;
+        Ok(c)
+    }
+}
+
+/// Parses a TOML integer, which can not be negative here.
+fn parse_int(token: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid value `{token}`");
+    let digits = token.strip_prefix('+').unwrap_or(token);
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+    // Underscores must be between digits, and decimal integers can not have
+    // leading zeros.
+    if digits.starts_with('_')
+        || digits.ends_with('_')
+        || digits.contains("__")
+        || (radix == 10 && digits.len() > 1 && digits.starts_with('0'))
+    {
+        return Err(invalid());
+    }
+    if radix == 10 && token.starts_with('-') {
+        let value = parse_int(&token[1..])?;
+        return match value {
+            0 => Ok(0),
+            _ => Err(format!(
+                "expected a non-negative integer but found `{token}`"
+            )),
+        };
+    }
+    let digits = digits.replace('_', "");
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(invalid());
+    }
+    u64::from_str_radix(&digits, radix).map_err(|_| invalid())
+}
@@ -2,40 +2,79 @@ use std::any::Any;
 use std::collections::HashMap;
 use std::fmt;
 use std::io::ErrorKind;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::time::Duration;
 
-use log::info;
+use vbase_file::journal::MAX_RECORD_SIZE;
 use vbase_file::journal::RecordWriter;
 use vbase_util::cell::UnsafeCell;
+use vbase_util::codec::BytesEncoder;
 use vbase_util::codec::Decoder;
+use vbase_util::codec::Encoder;
+use vbase_util::codec::Varint;
+use vbase_util::failpoint;
+use vbase_util::rate_limiter::RateLimiter;
+use vbase_util::span;
 use vbase_util::sync::Arc;
 use vbase_util::sync::Mutex;
 use vbase_util::sync::MutexGuard;
+use vbase_util::sync::atomic::AtomicBool;
+use vbase_util::sync::atomic::AtomicU64;
+use vbase_util::sync::atomic::Ordering::Acquire;
+use vbase_util::sync::atomic::Ordering::Relaxed;
+use vbase_util::sync::atomic::Ordering::Release;
 
 use crate::Error;
 use crate::Result;
 use crate::engine::Bucket;
 use crate::engine::Engine;
-use crate::engine::internal::BucketHandle;
-use crate::engine::internal::EngineHandle;
-use crate::engine::internal::Reader;
-use crate::engine::internal::Writer;
+use crate::engine::Property;
+use crate::engine::ext::BucketHandle;
+use crate::engine::ext::EngineHandle;
+use crate::engine::ext::Reader;
+use crate::engine::ext::Writer;
+use crate::engine::scheduler::ThreadPool;
 use crate::error::Corrupted;
+use crate::file::Identity;
 use crate::file::RootDir;
+use crate::info_log::event;
 use crate::journal::JournalWriter;
 use crate::manifest::Desc;
 use crate::manifest::EngineDesc;
 use crate::options::Builder;
 use crate::options::Options;
+use crate::options::VerifyOptions;
+use crate::options::VerifyScope;
 use crate::options::WriteOptions;
 use crate::pipeline::WriteCommitter;
 use crate::pipeline::WriteSubmitter;
 use crate::pipeline::create_pipeline;
 
 /// The core database structure.
+///
+/// Fields that own threads or files are dropped manually, so that a forked
+/// child process can leave them to its parent, see [`Core::drop`].
 pub struct Core {
-    root: RootDir,
+    /// Background threads of engines.
+    ///
+    /// This is dropped first, so that no job runs after the database is
+    /// closed.
+    pool: ManuallyDrop<ThreadPool>,
+    root: ManuallyDrop<RootDir>,
     options: Options,
-    engines: Engines,
+    engines: ManuallyDrop<Engines>,
+    identity: Identity,
+    /// The process that opened the database.
+    ///
+    /// A forked child process shares the journal writer, the pipeline and
+    /// the lock with its parent, so it must not use the database.
+    pid: u32,
+    /// Set when a write fails after its record may be in the journal.
+    ///
+    /// Such a write is not applied to the engines but is recovered on the
+    /// next open, so the database refuses to be used until it is reopened.
+    poisoned: AtomicBool,
 
     /// Writes are processed in a pipeline to improve throughput.
     ///
@@ -52,16 +91,22 @@ pub struct Core {
     /// 4. Unlock the journal
     /// 5. Update the engines
     /// 6. Commit the write to the committer
-    journal: Mutex<JournalWriter>,
+    journal: ManuallyDrop<Mutex<JournalWriter>>,
     submitter: UnsafeCell<WriteSubmitter>,
-    committer: WriteCommitter,
+    committer: ManuallyDrop<WriteCommitter>,
+
+    /// Cleared batches kept for reuse, up to `Options::batch_pool_size`.
+    batch_pool: Mutex<Vec<WriteBatch>>,
+
+    /// Counters of delayed and rejected writes.
+    stalls: StallCounters,
 }
 
 impl Core {
     pub fn open(path: &str, options: Options, mut builder: Builder) -> Result<Self> {
         options.validate()?;
         builder.validate()?;
-        info!("open {path} with {options:#?}");
+        span!("open", path);
 
         // Open or create `path`.
         let dir = match options.env.open_dir(path) {
@@ -72,10 +117,12 @@ impl Core {
             }
             Err(_) => options.env.create_dir(path)?,
         };
-        let root = RootDir::lock(dir)?;
+        let root = RootDir::lock(path, dir, &options)?;
+        event!(root, "open {path} with {options:#?}");
+        let pool = ThreadPool::new(options.high_priority_threads, options.low_priority_threads);
 
         // Read the manifest file.
-        let mut desc = match root.read_manifest()? {
+        let mut desc = match root.load_manifest()? {
             Some(_) if builder.error_if_exists => {
                 return Err(Error::Exists(format!("manifest in {path}")));
             }
@@ -86,15 +133,73 @@ impl Core {
             None => Desc::default(),
         };
 
-        // Clean up uncommitted engines.
+        // Complete engines that were being archived.
         let list = root.list()?;
+        let archived = list
+            .archived_engines
+            .iter()
+            .copied()
+            .filter(|&id| desc.engines.iter().any(|e| e.id == id))
+            .collect::<Vec<_>>();
+        for &id in &archived {
+            event!(root, "complete archiving engine {id}");
+            // The engine was opened before, so it has not persisted any write
+            // that we know of.
+            desc.archive_engine(id, 0);
+        }
+
+        // Clean up temporary files and uncommitted engines.
+        for name in list.temp_files {
+            event!(root, "delete temporary file {name}");
+            root.delete_temp_file(&name)?;
+        }
         for id in list.engines {
-            if !desc.engines.iter().any(|e| e.id == id) {
-                info!("delete uncommitted engine {id}");
+            if desc.engines.iter().any(|e| e.id == id) {
+                continue;
+            }
+            // Move back an archived engine that was being restored.
+            if let Some(engine) = desc.archived_engines.iter().find(|e| e.id == id) {
+                event!(root, "revert restoring engine {id}");
+                root.archive_engine(id, &engine.name)?;
+            } else {
+                event!(root, "delete uncommitted engine {id}");
                 root.delete_engine(id)?;
             }
         }
 
+        // Restore archived engines in the builder. The directory is moved
+        // before the manifest is updated, so that an interrupted restore can
+        // be reverted on the next open.
+        let mut restored = Vec::new();
+        for name in &builder.restore_engines {
+            let is_live = desc.engines.iter().any(|e| &e.name == name);
+            let archived = desc
+                .archived_engines
+                .iter()
+                .filter(|e| &e.name == name)
+                .max_by_key(|e| e.id);
+            match (is_live, archived) {
+                (true, None) => {}
+                (true, Some(_)) => {
+                    return Err(Error::Exists(format!(
+                        "engine {name} exists, so the archived one can not be restored",
+                    )));
+                }
+                (false, None) => {
+                    return Err(Error::NotExist(format!("archived engine {name}")));
+                }
+                (false, Some(engine)) => {
+                    let id = engine.id;
+                    event!(root, "restore engine {name} with id {id}");
+                    root.restore_engine(id, name)?;
+                    restored.push(id);
+                }
+            }
+        }
+        for &id in &restored {
+            desc.restore_engine(id);
+        }
+
         // Validate engines in the builder.
         for name in desc.engines.iter().map(|e| &e.name) {
             if !builder.engines.contains_key(name) {
@@ -106,60 +211,128 @@ impl Core {
 
         // Open or create engines in the builder.
         let mut engines = HashMap::new();
+        let mut created = Vec::new();
+        let mut last_id = desc.last_id;
         for (name, open) in builder.engines.drain() {
             let (id, dir) = match desc.engines.iter().find(|e| e.name == name) {
                 Some(engine) => {
-                    info!("open engine {} with id {}", engine.name, engine.id);
+                    event!(root, "open engine {} with id {}", engine.name, engine.id);
                     let id = engine.id;
-                    let dir = root.open_engine(id)?;
+                    let dir = root.open_engine(id, &name)?;
                     (id, dir)
                 }
                 None => {
-                    let id = desc.last_id + 1;
+                    let id = last_id + 1;
                     let engine = EngineDesc {
                         id,
                         name: name.clone(),
+                        persisted_lsn: 0,
                     };
-                    info!("create engine {} with id {}", engine.name, engine.id);
-                    desc.last_id = id;
-                    desc.engines.push(engine);
-                    let dir = root.create_engine(id)?;
+                    event!(root, "create engine {} with id {}", engine.name, engine.id);
+                    last_id = id;
+                    created.push(engine);
+                    let dir = root.create_engine(id, &name)?;
                     (id, dir)
                 }
             };
             let handle = open(id, dir)?;
-            engines.insert(id, handle);
+            handle.set_scheduler(pool.scheduler());
+            engines.insert(id, OpenedEngine::new(handle));
         }
 
-        // Commit created engines to the manifest.
-        root.switch_manifest(&desc)?;
+        // Commit created, archived and restored engines to the manifest.
+        root.update_manifest(|desc| {
+            desc.last_id = last_id;
+            desc.engines.extend(created);
+            for id in archived {
+                desc.archive_engine(id, 0);
+            }
+            for id in restored {
+                desc.restore_engine(id);
+            }
+            Ok(())
+        })?;
 
         // Recover to the previous state.
-        let mut recover = Recover::new(root, Engines(engines));
-        recover.recover()?;
+        let mut recover = Recover::new(root, Engines(engines), &desc);
+        recover
+            .recover()
+            .inspect_err(|e| event!(recover.root, "failed to recover: {e}"))?;
         let Recover {
             root,
             engines,
             last_lsn,
+            ..
         } = recover;
-        let journal = root.create_journal(last_lsn + 1)?;
-        let (submitter, committer) = create_pipeline(last_lsn);
+        // Bump the epoch, so that records written from now on can be told
+        // apart from those of any other process that has opened the database.
+        let epoch = desc.epoch + 1;
+        root.update_manifest(|desc| {
+            // Records written from now on carry batch versions and epochs.
+            if desc.versioned_lsn == 0 {
+                desc.versioned_lsn = last_lsn + 1;
+            }
+            if desc.fenced_lsn == 0 {
+                desc.fenced_lsn = last_lsn + 1;
+            }
+            desc.epoch = epoch;
+            Ok(())
+        })?;
+        event!(root, "open with epoch {epoch}");
+        // Keep the effective options for debugging.
+        root.write_options(epoch, &options.to_toml())?;
+        let identity = root.update_identity(engines.iter().map(|e| e.name().into()).collect())?;
+        event!(root, "open database {}", identity.id());
+        root.purge_trash(pool.scheduler())?;
+        let journal = root.create_journal(last_lsn + 1, epoch)?;
+        let (submitter, committer) = create_pipeline(last_lsn, options.background_publish);
 
         Ok(Self {
-            root,
+            pool: ManuallyDrop::new(pool),
+            root: ManuallyDrop::new(root),
             options,
-            engines,
-            journal: Mutex::new(journal),
+            engines: ManuallyDrop::new(engines),
+            identity,
+            pid: std::process::id(),
+            poisoned: AtomicBool::new(false),
+            journal: ManuallyDrop::new(Mutex::new(journal)),
             submitter: UnsafeCell::new(submitter),
-            committer,
+            committer: ManuallyDrop::new(committer),
+            batch_pool: Mutex::new(Vec::new()),
+            stalls: StallCounters::default(),
         })
     }
 
-    pub fn read<B: Bucket>(&self, bucket: &B) -> B::Reader<'_> {
-        B::Reader::new(bucket.handle().id())
+    pub fn read<B: Bucket>(&self, bucket: &B) -> Result<B::Reader<'_>> {
+        self.read_at(bucket, self.committer.last_lsn())
+    }
+
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot {
+            core: self,
+            lsn: self.committer.last_lsn(),
+        }
     }
 
     pub fn write(&self, batch: &WriteBatch, options: &WriteOptions) -> Result<()> {
+        self.check_poisoned()?;
+        let size = batch.size();
+        span!("write", size);
+        if size > self.options.max_batch_size {
+            return Err(Error::InvalidArgument(format!(
+                "batch size {size} exceeds `max_batch_size` {}",
+                self.options.max_batch_size
+            )));
+        }
+        // Reject a batch that does not fit in a journal record before writing
+        // anything, otherwise the journal is left with an incomplete record.
+        if 2 * u64::MAX_VARINT_SIZE + batch.encoded_size() > MAX_RECORD_SIZE {
+            return Err(Error::InvalidArgument(format!(
+                "batch size {size} exceeds the maximum journal record size {MAX_RECORD_SIZE}"
+            )));
+        }
+        self.throttle()?;
+
         /// A guard that protects the journal and the submitter.
         ///
         /// The submitter requires exclusive access, but we can not put it in
@@ -176,12 +349,16 @@ impl Core {
                 // SAFETY: we have exclusive access to the submitter
                 submitter: unsafe { self.submitter.as_mut() },
             };
+            // Engines are archived with the journal lock, so no write to an
+            // archived engine gets through this check.
+            self.engines.check_write(batch)?;
             let lsn = guard.submitter.next_lsn();
-            guard.journal.write(lsn, |record| batch.append(record))?;
-            if options.sync {
-                guard.journal.sync()?;
+            self.rotate_journal(&mut guard.journal, lsn)?;
+            if let Err(e) = self.append_journal(&mut guard.journal, lsn, batch, options) {
+                self.poisoned.store(true, Release);
+                event!(self.root, "poison the database after a failed write: {e}");
+                return Err(e);
             }
-            // TODO: handle journal rotation
             let handle = guard.submitter.submit(lsn);
             (lsn, handle)
         };
@@ -191,6 +368,76 @@ impl Core {
         Ok(())
     }
 
+    pub fn property(&self, name: &str) -> Result<Option<Property>> {
+        let Some(name) = name.strip_prefix("vbase.") else {
+            return Ok(None);
+        };
+        let value = match name {
+            "id" => Property::Str(self.identity.id().into()),
+            "last-lsn" => Property::Int(self.committer.last_lsn()),
+            "num-journals" => Property::Int(self.root.list()?.journals.len() as u64),
+            "memtable-bytes" => Property::Int(self.engines.memory_usage() as u64),
+            "delayed-writes" => Property::Int(self.stalls.delayed_writes.load(Relaxed)),
+            "rejected-writes" => Property::Int(self.stalls.rejected_writes.load(Relaxed)),
+            "journal-queue-depth" => {
+                Property::Int(self.journal.lock().unwrap().queue_depth() as u64)
+            }
+            _ => {
+                let Some((engine, name)) = name.split_once('.') else {
+                    return Ok(None);
+                };
+                return Ok(self
+                    .engines
+                    .iter()
+                    .find(|e| e.name().eq_ignore_ascii_case(engine))
+                    .and_then(|e| e.property(name)));
+            }
+        };
+        Ok(Some(value))
+    }
+
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    pub fn write_stall_stats(&self) -> WriteStallStats {
+        WriteStallStats {
+            delayed_writes: self.stalls.delayed_writes.load(Relaxed),
+            delay: Duration::from_micros(self.stalls.delay_micros.load(Relaxed)),
+            rejected_writes: self.stalls.rejected_writes.load(Relaxed),
+        }
+    }
+
+    pub fn batch(&self) -> WriteBatch {
+        self.batch_pool.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    pub fn recycle_batch(&self, mut batch: WriteBatch) {
+        let mut pool = self.batch_pool.lock().unwrap();
+        if pool.len() < self.options.batch_pool_size {
+            batch.clear();
+            pool.push(batch);
+        }
+    }
+
+    pub fn engine<E: Engine>(&self) -> Result<EngineRef<'_, E>> {
+        let Some(engine) = self.engines.find(E::NAME) else {
+            return Err(Error::InvalidArgument(format!(
+                "engine {} is not registered",
+                E::NAME
+            )));
+        };
+
+        let engine = engine as &dyn Any;
+        let Some(handle) = engine.downcast_ref() else {
+            return Err(Error::InvalidArgument(format!(
+                "invalid handle for engine {}",
+                E::NAME
+            )));
+        };
+        Ok(EngineRef(handle))
+    }
+
     pub fn bucket<E: Engine>(&self, name: &str) -> Result<E::Bucket> {
         let Some(engine) = self.engines.find(E::NAME) else {
             return Err(Error::InvalidArgument(format!(
@@ -211,7 +458,8 @@ impl Core {
             )));
         };
 
-        info!("create bucket {name} in engine {}", E::NAME);
+        self.check_poisoned()?;
+        event!(self.root, "create bucket {name} in engine {}", E::NAME);
         let handle = engine.create_bucket(name)?;
         open_bucket::<E, E::Bucket>(handle)
     }
@@ -224,9 +472,251 @@ impl Core {
             )));
         };
 
-        info!("delete bucket {name} from engine {}", E::NAME);
+        self.check_poisoned()?;
+        event!(self.root, "delete bucket {name} from engine {}", E::NAME);
         engine.delete_bucket(name)
     }
+
+    pub fn archive_engine<E: Engine>(&self) -> Result<()> {
+        self.check_poisoned()?;
+        let Some(engine) = self.engines.find(E::NAME) else {
+            return Err(Error::InvalidArgument(format!(
+                "engine {} is not registered",
+                E::NAME
+            )));
+        };
+
+        let id = engine.id();
+        event!(self.root, "archive engine {} with id {id}", E::NAME);
+        // Stop writing to the engine before moving its directory. Writes that
+        // the engine has not persisted are only in the journals, so they are
+        // kept with the persisted LSN in the manifest until the engine is
+        // restored. The directory is moved before the manifest is updated, so
+        // that an interrupted archive can be completed on the next open
+        // instead of deleting the engine.
+        let _journal = self.journal.lock().unwrap();
+        self.engines.set_archived(id, true);
+        if let Err(e) = self.root.archive_engine(id, E::NAME) {
+            self.engines.set_archived(id, false);
+            return Err(e);
+        }
+        let persisted_lsn = self.engines.persisted_lsn(id);
+        self.root.update_manifest(|desc| {
+            desc.archive_engine(id, persisted_lsn);
+            Ok(())
+        })
+    }
+
+    pub fn verify_files(&self, scope: &VerifyScope, options: &VerifyOptions) -> Result<()> {
+        self.check_poisoned()?;
+        event!(self.root, "verify files in {scope:?}");
+        let limiter = RateLimiter::with_clock(options.rate_limit, self.options.clock.clone());
+        match scope {
+            VerifyScope::All => {
+                self.root.verify_manifest()?;
+                self.verify_journals(&limiter)?;
+                self.engines.verify_files(scope, &limiter)
+            }
+            VerifyScope::Journals => self.verify_journals(&limiter),
+            VerifyScope::Manifests => {
+                self.root.verify_manifest()?;
+                self.engines.verify_files(scope, &limiter)
+            }
+            VerifyScope::Bucket { engine_id, .. } => {
+                let Some(engine) = self.engines.get(*engine_id) else {
+                    return Err(Error::NotExist(format!("engine {engine_id}")));
+                };
+                engine.verify_files(scope, &limiter)
+            }
+        }
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.check_poisoned()?;
+        if self.options.in_memory {
+            return Ok(());
+        }
+        let mut journal = self.journal.lock().unwrap();
+        journal.sync()?;
+        self.root.sync_journal(journal.id(), journal.size())
+    }
+
+    pub fn backup_files(&self, copy: &mut dyn FnMut(&str, &[u8]) -> Result<()>) -> Result<()> {
+        self.check_poisoned()?;
+        let desc = self.root.backup_manifest(copy)?;
+        for e in &desc.engines {
+            let Some(engine) = self.engines.get(e.id) else {
+                return Err(Error::InvalidArgument(format!(
+                    "engine {} is archived during the backup",
+                    e.name
+                )));
+            };
+            self.root.backup_engine(e.id, engine, copy)?;
+        }
+        // Copy the journals after the engines, so that they cover all writes
+        // in the engine files. Journals are only deleted on open, so they can
+        // be read without the lock.
+        let (id, size) = {
+            let mut journal = self.journal.lock().unwrap();
+            journal.sync()?;
+            (journal.id(), journal.size())
+        };
+        self.root.backup_journals(id, size, copy)
+    }
+}
+
+impl Core {
+    fn read_at<B: Bucket>(&self, bucket: &B, lsn: u64) -> Result<B::Reader<'_>> {
+        self.check_poisoned()?;
+        let handle = bucket.handle();
+        let Some(engine) = self.engines.get_opened(handle.engine_id()) else {
+            return Err(Error::InvalidArgument(format!(
+                "engine {} is not opened",
+                handle.engine_id()
+            )));
+        };
+        let engine = engine as &dyn Any;
+        let Some(engine) = engine.downcast_ref() else {
+            return Err(Error::InvalidArgument(format!(
+                "invalid engine handle for bucket {}",
+                handle.id()
+            )));
+        };
+        Ok(B::Reader::new(engine, handle.id(), lsn))
+    }
+
+    /// Delays or rejects a write if engines use too much memory.
+    fn throttle(&self) -> Result<()> {
+        let options = &self.options;
+        if options.write_slowdown_size == usize::MAX && options.write_stop_size == usize::MAX {
+            return Ok(());
+        }
+        let usage = self.engines.memory_usage();
+        if usage >= options.write_stop_size && self.engines.releases_memory() {
+            self.stalls.rejected_writes.fetch_add(1, Relaxed);
+            return Err(Error::Busy(format!(
+                "memory usage {usage} exceeds `write_stop_size` {}",
+                options.write_stop_size
+            )));
+        }
+        if usage >= options.write_slowdown_size {
+            span!("write_stall", usage);
+            options.clock.sleep(options.write_slowdown_delay);
+            self.stalls.delayed_writes.fetch_add(1, Relaxed);
+            self.stalls
+                .delay_micros
+                .fetch_add(options.write_slowdown_delay.as_micros() as u64, Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Switches to a new journal starting at `lsn` if the current one is full.
+    fn rotate_journal(&self, journal: &mut JournalWriter, lsn: u64) -> Result<()> {
+        if journal.size() < self.options.journal_file_size as u64 {
+            return Ok(());
+        }
+        span!("rotate_journal", from = journal.id(), to = lsn);
+        // Synchronize the full journal before switching, otherwise a crash
+        // may lose its tail while later writes in the new one survive.
+        journal.sync()?;
+        self.root.sync_journal(journal.id(), journal.size())?;
+        event!(
+            self.root,
+            "switch from journal {} to journal {lsn}",
+            journal.id()
+        );
+        *journal = self.root.create_journal(lsn, journal.epoch())?;
+        Ok(())
+    }
+
+    /// Appends a batch to the journal, synchronizing it if required.
+    ///
+    /// The record may be in the journal if this fails, so the failure can not
+    /// be undone.
+    fn append_journal(
+        &self,
+        journal: &mut JournalWriter,
+        lsn: u64,
+        batch: &WriteBatch,
+        options: &WriteOptions,
+    ) -> Result<()> {
+        batch.write_journal(journal, lsn, &self.engines)?;
+        if options.sync && !self.options.in_memory {
+            journal.sync()?;
+            self.root.sync_journal(journal.id(), journal.size())?;
+        }
+        failpoint::check("core::write::before_submit")?;
+        Ok(())
+    }
+
+    /// Checks that the database is used by the process that opened it and is
+    /// not poisoned by a failed write.
+    fn check_poisoned(&self) -> Result<()> {
+        let pid = std::process::id();
+        if pid != self.pid {
+            return Err(Error::Poisoned(
+                self.root.path().into(),
+                format!("opened by process {} but used by process {pid}", self.pid),
+            ));
+        }
+        if self.poisoned.load(Acquire) {
+            return Err(Error::Poisoned(
+                self.root.path().into(),
+                "a write failed after it was appended to the journal".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn verify_journals(&self, limiter: &RateLimiter) -> Result<()> {
+        // The active journal is being written, so only verify the part that
+        // has been written so far.
+        let (active_id, active_size) = {
+            let mut journal = self.journal.lock().unwrap();
+            if self.options.journal_write_queue > 0 {
+                // Make sure that queued records are written.
+                journal.sync()?;
+            }
+            (journal.id(), journal.size())
+        };
+        for id in self.root.list()?.journals {
+            let mut journal = match self.root.open_journal(id) {
+                Ok(journal) => journal,
+                // The journal has been deleted after listing.
+                Err(Error::Io(e)) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            if id == active_id {
+                journal = journal.limit(active_size);
+            }
+            while let Some((_, batch)) = journal.read()? {
+                limiter.request(batch.len());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Core {
+    /// Closes the database.
+    ///
+    /// In a forked child process, the threads and files are left to the
+    /// parent instead. Joining threads that do not exist in the child fails,
+    /// and syncing or unlocking the shared files would interfere with the
+    /// parent.
+    fn drop(&mut self) {
+        if std::process::id() != self.pid {
+            return;
+        }
+        // SAFETY: the fields are never used again.
+        unsafe {
+            ManuallyDrop::drop(&mut self.pool);
+            ManuallyDrop::drop(&mut self.root);
+            ManuallyDrop::drop(&mut self.engines);
+            ManuallyDrop::drop(&mut self.journal);
+            ManuallyDrop::drop(&mut self.committer);
+        }
+    }
 }
 
 impl fmt::Debug for Core {
@@ -250,42 +740,145 @@ where
     Ok(B::open(handle))
 }
 
-struct Engines(HashMap<u64, Box<dyn EngineHandle>>);
+struct Engines(HashMap<u64, OpenedEngine>);
+
+struct OpenedEngine {
+    handle: Box<dyn EngineHandle>,
+    /// The last LSN persisted by the engine when it is opened.
+    persisted_lsn: u64,
+    /// Archived engines are kept until the database is closed, since readers
+    /// may still refer to them, but they are not used for new operations.
+    is_archived: AtomicBool,
+}
+
+impl OpenedEngine {
+    fn new(handle: Box<dyn EngineHandle>) -> Self {
+        Self {
+            persisted_lsn: handle.last_lsn(),
+            handle,
+            is_archived: AtomicBool::new(false),
+        }
+    }
+}
 
 impl Engines {
+    /// Gets an engine by id.
+    fn get(&self, id: u64) -> Option<&dyn EngineHandle> {
+        self.0
+            .get(&id)
+            .filter(|e| !e.is_archived.load(Acquire))
+            .map(|e| e.handle.as_ref())
+    }
+
+    /// Gets an engine by id, including archived ones.
+    fn get_opened(&self, id: u64) -> Option<&dyn EngineHandle> {
+        self.0.get(&id).map(|e| e.handle.as_ref())
+    }
+
     /// Finds an engine.
-    fn find(&self, name: &str) -> Option<&Box<dyn EngineHandle>> {
-        self.0.values().find(|h| h.name() == name)
+    fn find(&self, name: &str) -> Option<&dyn EngineHandle> {
+        self.iter().find(|h| h.name() == name)
+    }
+
+    /// Iterates over engines that are not archived.
+    fn iter(&self) -> impl Iterator<Item = &dyn EngineHandle> {
+        self.0
+            .values()
+            .filter(|e| !e.is_archived.load(Acquire))
+            .map(|e| e.handle.as_ref())
+    }
+
+    /// Marks an engine as archived or not.
+    fn set_archived(&self, id: u64, archived: bool) {
+        if let Some(engine) = self.0.get(&id) {
+            engine.is_archived.store(archived, Release);
+        }
+    }
+
+    /// Returns the last LSN persisted by engine `id` when it is opened.
+    fn persisted_lsn(&self, id: u64) -> u64 {
+        self.0.get(&id).map_or(0, |e| e.persisted_lsn)
+    }
+
+    /// Returns an error if a batch writes to an archived engine.
+    fn check_write(&self, batch: &WriteBatch) -> Result<()> {
+        for (id, _) in batch.iter() {
+            if let Some(engine) = self.0.get(&id)
+                && engine.is_archived.load(Acquire)
+            {
+                return Err(Error::InvalidArgument(format!(
+                    "engine {} is archived",
+                    engine.handle.name()
+                )));
+            }
+        }
+        Ok(())
     }
 
     /// Writes a batch to engines.
+    ///
+    /// Writes that have passed [`Self::check_write`] are applied to archived
+    /// engines too, so that their readers see all of them.
     fn write(&self, lsn: u64, batch: &WriteBatch) {
-        for (id, batch) in &batch.engines {
-            if let Some(engine) = self.0.get(id) {
+        for (id, batch) in batch.iter() {
+            if let Some(engine) = self.get_opened(id) {
                 engine.write(lsn, batch);
             }
         }
     }
 
+    /// Returns the batch version of engine `id`.
+    ///
+    /// Batches for engines that are not opened are ignored, so their version
+    /// does not matter.
+    fn batch_version(&self, id: u64) -> u8 {
+        self.get(id).map_or(0, |e| e.batch_version())
+    }
+
     /// Recovers engines from a write batch.
-    fn recover(&self, lsn: u64, batch: &[u8]) {
-        for (id, batch) in WriteBatchIter(batch) {
-            if let Some(engine) = self.0.get(&id) {
-                if engine.last_lsn() < lsn {
-                    engine.write(lsn, batch);
+    fn recover(&self, lsn: u64, batch: WriteBatchIter<'_>) -> Result<()> {
+        for (id, version, batch) in batch {
+            if let Some(engine) = self.get(id)
+                && engine.last_lsn() < lsn
+            {
+                if !engine.supports_batch_version(version) {
+                    return Err(Error::InvalidArgument(format!(
+                        "engine {} does not support batch version {version} at LSN {lsn}",
+                        engine.name()
+                    )));
                 }
+                engine.replay(lsn, version, batch);
             }
         }
+        Ok(())
+    }
+
+    /// Returns the total memory usage of all engines.
+    fn memory_usage(&self) -> usize {
+        self.iter().map(|e| e.memory_usage()).sum()
+    }
+
+    /// Returns true if all engines release memory in the background.
+    fn releases_memory(&self) -> bool {
+        self.iter().all(|e| e.releases_memory())
+    }
+
+    /// Verifies files of all engines.
+    fn verify_files(&self, scope: &VerifyScope, limiter: &RateLimiter) -> Result<()> {
+        for engine in self.iter() {
+            engine.verify_files(scope, limiter)?;
+        }
+        Ok(())
     }
 
     /// Returns the minimum last LSN among all engines.
     fn min_last_lsn(&self) -> u64 {
-        self.0.values().map(|e| e.last_lsn()).min().unwrap_or(0)
+        self.iter().map(|e| e.last_lsn()).min().unwrap_or(0)
     }
 
     /// Returns the maximum last LSN among all engines.
     fn max_last_lsn(&self) -> u64 {
-        self.0.values().map(|e| e.last_lsn()).max().unwrap_or(0)
+        self.iter().map(|e| e.last_lsn()).max().unwrap_or(0)
     }
 }
 
@@ -293,36 +886,84 @@ struct Recover {
     root: RootDir,
     engines: Engines,
     last_lsn: u64,
+    /// The first LSN of records with batch versions.
+    versioned_lsn: u64,
+    /// The first LSN of records with epochs.
+    fenced_lsn: u64,
+    /// The epoch of the last database that opened successfully.
+    epoch: u64,
+    /// The epoch of the last recovered record.
+    last_epoch: u64,
+    /// The minimum LSN persisted by archived engines.
+    archived_lsn: u64,
 }
 
 impl Recover {
-    fn new(root: RootDir, engines: Engines) -> Self {
+    fn new(root: RootDir, engines: Engines, desc: &Desc) -> Self {
+        // No record has batch versions or epochs if the LSNs are not set.
+        let first_lsn = |lsn| if lsn == 0 { u64::MAX } else { lsn };
         Self {
             root,
             engines,
             last_lsn: 0,
+            versioned_lsn: first_lsn(desc.versioned_lsn),
+            fenced_lsn: first_lsn(desc.fenced_lsn),
+            epoch: desc.epoch,
+            last_epoch: 0,
+            archived_lsn: desc
+                .archived_engines
+                .iter()
+                .map(|e| e.persisted_lsn)
+                .min()
+                .unwrap_or(u64::MAX),
         }
     }
 
     fn recover(&mut self) -> Result<()> {
         let min_lsn = self.engines.min_last_lsn();
+        span!("recover", min_lsn);
         let journals = self.journals_to_recover(min_lsn)?;
         self.last_lsn = min_lsn;
 
         for id in journals.iter().cloned() {
-            info!("recover from journal {id}");
+            event!(self.root, "recover from journal {id}");
+            span!("recover_journal", id);
             let mut journal = self.root.open_journal(id)?;
-            while let Some((lsn, batch)) = journal.read()? {
+            while let Some((lsn, mut batch)) = journal.read()? {
                 if lsn <= min_lsn {
                     continue;
                 }
+                if lsn >= self.fenced_lsn {
+                    let epoch = batch.decode_varint::<u64>();
+                    if epoch > self.epoch {
+                        return journal.path().corrupted(format!(
+                            "record at LSN {lsn} has epoch {epoch}, which is newer than \
+                            the epoch {} in the manifest, so another process may have \
+                            written the database concurrently",
+                            self.epoch,
+                        ));
+                    }
+                    // Epochs never go back, unless records of a process that
+                    // has lost the lock are interleaved with later ones.
+                    if epoch < self.last_epoch {
+                        return journal.path().corrupted(format!(
+                            "record at LSN {lsn} has epoch {epoch}, which is older than \
+                            the epoch {} of the previous record, so another process may \
+                            have written the database concurrently",
+                            self.last_epoch,
+                        ));
+                    }
+                    self.last_epoch = epoch;
+                }
                 if lsn != self.last_lsn + 1 {
                     return journal.path().corrupted(format!(
                         "unexpected LSN {}, the previous LSN is {}",
                         lsn, self.last_lsn,
                     ));
                 }
-                self.engines.recover(lsn, batch);
+                let versioned = lsn >= self.versioned_lsn;
+                self.engines
+                    .recover(lsn, WriteBatchIter::new(batch, versioned))?;
                 self.last_lsn = lsn;
             }
         }
@@ -337,8 +978,26 @@ impl Recover {
             ));
         }
 
+        // Engines only persist writes up to their last LSNs before recovery,
+        // so journals with later writes must be kept for the next recovery.
+        // Without engines, no journal is needed anymore. Archived engines
+        // need their journals until they are restored.
         // TODO: flush engines.
-        for id in journals {
+        let persisted_lsn = if self.engines.iter().next().is_some() {
+            min_lsn
+        } else {
+            self.last_lsn
+        };
+        let persisted_lsn = persisted_lsn.min(self.archived_lsn);
+        let ends = journals
+            .iter()
+            .skip(1)
+            .map(|&id| id - 1)
+            .chain([self.last_lsn]);
+        for (id, end) in journals.iter().copied().zip(ends) {
+            if end > persisted_lsn {
+                break;
+            }
             self.root.delete_journal(id)?;
         }
         Ok(())
@@ -356,6 +1015,97 @@ impl Recover {
     }
 }
 
+/// A consistent view of the database.
+///
+/// Readers created from a snapshot only see writes published before the
+/// snapshot is taken, no matter which bucket they read.
+pub struct Snapshot<'a> {
+    core: &'a Core,
+    lsn: u64,
+}
+
+impl<'a> Snapshot<'a> {
+    /// Returns the last LSN visible to the snapshot.
+    pub fn lsn(&self) -> u64 {
+        self.lsn
+    }
+
+    /// Returns a reader for the bucket at the snapshot.
+    ///
+    /// # Errors
+    ///
+    /// - Returns [`Error::InvalidArgument`] if the engine of the bucket is not
+    ///   opened in the database.
+    /// - Returns [`Error::Poisoned`] if the database is used by a forked
+    ///   process or poisoned by a failed write.
+    pub fn read<B: Bucket>(&self, bucket: &B) -> Result<B::Reader<'a>> {
+        self.core.read_at(bucket, self.lsn)
+    }
+}
+
+impl fmt::Debug for Snapshot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshot").field("lsn", &self.lsn).finish()
+    }
+}
+
+#[derive(Default)]
+struct StallCounters {
+    delayed_writes: AtomicU64,
+    delay_micros: AtomicU64,
+    rejected_writes: AtomicU64,
+}
+
+/// Statistics of stalled writes.
+///
+/// See [`Options::write_slowdown_size`] and [`Options::write_stop_size`].
+#[derive(Clone, Debug)]
+pub struct WriteStallStats {
+    delayed_writes: u64,
+    delay: Duration,
+    rejected_writes: u64,
+}
+
+impl WriteStallStats {
+    /// Returns the number of delayed writes.
+    pub fn delayed_writes(&self) -> u64 {
+        self.delayed_writes
+    }
+
+    /// Returns the total time that writes have been delayed.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Returns the number of writes rejected with [`Error::Busy`].
+    pub fn rejected_writes(&self) -> u64 {
+        self.rejected_writes
+    }
+}
+
+/// A typed reference to an engine in the database.
+///
+/// This dereferences to the handle of the engine, which exposes APIs that are
+/// specific to the engine.
+pub struct EngineRef<'a, E: Engine>(&'a E::Handle);
+
+impl<E: Engine> Deref for EngineRef<'_, E> {
+    type Target = E::Handle;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<E: Engine> fmt::Debug for EngineRef<'_, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EngineRef")
+            .field("id", &self.0.id())
+            .field("name", &E::NAME)
+            .finish()
+    }
+}
+
 /// A batch of updates to the database.
 #[derive(Clone, Default)]
 pub struct WriteBatch {
@@ -379,31 +1129,99 @@ impl WriteBatch {
             .or_insert_with(|| Vec::with_capacity(4096));
         B::Writer::new(handle.id(), buffer)
     }
+
+    /// Returns the approximate size of the batch in bytes.
+    pub fn size(&self) -> usize {
+        self.engines.values().map(Vec::len).sum()
+    }
+
+    /// Removes all updates from the batch.
+    ///
+    /// The allocated buffers are kept, so that the batch can be reused
+    /// without allocating again.
+    pub fn clear(&mut self) {
+        self.engines.values_mut().for_each(Vec::clear);
+    }
 }
 
 impl WriteBatch {
+    /// Returns the size of the batch in a journal record.
+    fn encoded_size(&self) -> usize {
+        // Each engine has its id, batch version and batch.
+        self.iter()
+            .map(|(id, batch)| id.size() + 1 + batch.len().size() + batch.len())
+            .sum()
+    }
+
+    /// Writes the write batch with its LSN to a journal.
+    ///
+    /// Small batches are encoded on the stack and written in one call, which
+    /// takes most of the overhead out of point writes.
+    fn write_journal(
+        &self,
+        journal: &mut JournalWriter,
+        lsn: u64,
+        engines: &Engines,
+    ) -> Result<()> {
+        const SMALL_SIZE: usize = 256;
+        if lsn.size() + journal.epoch().size() + self.encoded_size() > SMALL_SIZE {
+            return journal.write(lsn, |record| self.append(record, engines));
+        }
+        let mut buf = [0; SMALL_SIZE];
+        let mut enc = BytesEncoder::new(&mut buf);
+        enc.encode_varint(lsn);
+        enc.encode_varint(journal.epoch());
+        for (id, batch) in self.iter() {
+            enc.encode_varint(id);
+            enc.put(engines.batch_version(id));
+            enc.encode(batch);
+        }
+        journal.write_record(enc.encoded_bytes())
+    }
+
     /// Appends the write batch to a record writer.
-    fn append(&self, record: &mut RecordWriter) -> Result<()> {
-        for (&id, batch) in &self.engines {
+    fn append(&self, record: &mut RecordWriter, engines: &Engines) -> Result<()> {
+        for (id, batch) in self.iter() {
             record.append_varint(id)?;
+            record.append(&[engines.batch_version(id)])?;
             record.append_varint_slice(batch)?;
         }
         Ok(())
     }
+
+    /// Iterates over the updates of each engine, skipping cleared ones.
+    fn iter(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.engines
+            .iter()
+            .filter(|(_, batch)| !batch.is_empty())
+            .map(|(&id, batch)| (id, batch.as_slice()))
+    }
 }
 
-/// An iterator over a write batch.
-struct WriteBatchIter<'a>(&'a [u8]);
+/// An iterator over a write batch in a journal record.
+struct WriteBatchIter<'a> {
+    data: &'a [u8],
+    /// Whether the batch of each engine has a version.
+    versioned: bool,
+}
+
+impl<'a> WriteBatchIter<'a> {
+    fn new(data: &'a [u8], versioned: bool) -> Self {
+        Self { data, versioned }
+    }
+}
 
 impl<'a> Iterator for WriteBatchIter<'a> {
-    type Item = (u64, &'a [u8]);
+    /// The engine id, the batch version and the batch.
+    type Item = (u64, u8, &'a [u8]);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.0.is_empty() {
+        if self.data.is_empty() {
             return None;
         }
-        let id = self.0.decode_varint();
-        let batch = self.0.decode();
-        Some((id, batch))
+        let id = self.data.decode_varint();
+        let version = if self.versioned { self.data.pop() } else { 0 };
+        let batch = self.data.decode();
+        Some((id, version, batch))
     }
 }
@@ -0,0 +1,201 @@
+//! Traits to implement database engines.
+//!
+//! See the `vbase-engine` crate for the contract between the database and
+//! engines.
+
+use std::any::Any;
+
+use vbase_env::boxed::Dir;
+use vbase_util::rate_limiter::RateLimiter;
+use vbase_util::sync::Arc;
+
+use crate::Result;
+use crate::engine::Property;
+use crate::engine::scheduler::Scheduler;
+use crate::options::VerifyScope;
+
+/// The version of the engine API.
+///
+/// This is bumped on every breaking change to the traits in this module or
+/// to the contract between the database and engines.
+pub const API_VERSION: u32 = 1;
+
+/// A database engine.
+pub trait Engine {
+    type Handle: EngineHandle;
+
+    /// The name of the engine.
+    const NAME: &str;
+
+    /// The version of the engine API that the engine implements.
+    ///
+    /// Engines should set this to [`API_VERSION`] of the version they are
+    /// built against. The database refuses to open an engine with a
+    /// different version.
+    const API_VERSION: u32;
+
+    /// Opens a handle to the engine.
+    fn open(id: u64, dir: Dir) -> Result<Self::Handle>;
+}
+
+/// A handle to an opened engine.
+pub trait EngineHandle: Any + Send + Sync + 'static {
+    /// Returns the id of the engine.
+    fn id(&self) -> u64;
+
+    /// Returns the name of the engine.
+    fn name(&self) -> &str;
+
+    /// Writes a batch with the given LSN.
+    ///
+    /// Writes to a deleted bucket should be ignored.
+    fn write(&self, lsn: u64, batch: &[u8]);
+
+    /// Returns the last LSN written to the engine.
+    fn last_lsn(&self) -> u64;
+
+    /// Returns the version of batches written by the engine.
+    ///
+    /// The version is stored with each batch in journals, so that the engine
+    /// can still replay batches written by its previous versions after the
+    /// batch format changes. Batches written before versioning have version
+    /// 0.
+    fn batch_version(&self) -> u8 {
+        0
+    }
+
+    /// Returns true if the engine can replay batches of `version`.
+    ///
+    /// Opening the database fails if a journal has a batch of an unsupported
+    /// version.
+    fn supports_batch_version(&self, version: u8) -> bool {
+        version == self.batch_version()
+    }
+
+    /// Replays a batch of `version` with the given LSN during recovery.
+    ///
+    /// This is only called with supported versions. The default
+    /// implementation writes the batch with [`Self::write`].
+    fn replay(&self, lsn: u64, version: u8, batch: &[u8]) {
+        let _ = version;
+        self.write(lsn, batch);
+    }
+
+    /// Returns a bucket if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::NotExist`] if the bucket does not exist.
+    fn bucket(&self, name: &str) -> Result<Arc<dyn BucketHandle>>;
+
+    /// Creates a bucket.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::Exists`] if the bucket already exists.
+    fn create_bucket(&self, name: &str) -> Result<Arc<dyn BucketHandle>>;
+
+    /// Deletes a bucket.
+    ///
+    /// Existing handles of the bucket must not see its data anymore, and the
+    /// id of the bucket must never be reused, even after reopening, so that
+    /// stale handles and journal records never apply to another bucket.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::NotExist`] if the bucket does not exist.
+    fn delete_bucket(&self, name: &str) -> Result<()>;
+
+    /// Verifies the checksums of files in `scope`.
+    ///
+    /// Files outside of `scope` should be skipped, and reads should be paced
+    /// with `limiter`. This function must not block writes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::Corrupted`] if any file is corrupted.
+    fn verify_files(&self, scope: &VerifyScope, limiter: &RateLimiter) -> Result<()>;
+
+    /// Passes the name and content of each file needed to open the engine to
+    /// `copy`.
+    ///
+    /// The files must form a consistent state of the engine, which may lag
+    /// behind the last LSN, since the journals are copied after the engines.
+    /// This function must not block writes.
+    fn backup_files(&self, copy: &mut dyn FnMut(&str, &[u8]) -> Result<()>) -> Result<()>;
+
+    /// Passes the scheduler of background jobs to the engine.
+    ///
+    /// This is called once right after the engine is opened, before it is
+    /// recovered. The default implementation drops the scheduler.
+    fn set_scheduler(&self, scheduler: Scheduler) {
+        let _ = scheduler;
+    }
+
+    /// Returns the value of property `name` of the engine, if any.
+    ///
+    /// The name does not include the `vbase.{engine}.` prefix, where
+    /// `{engine}` is the lowercase name of the engine. The default
+    /// implementation has no properties.
+    fn property(&self, name: &str) -> Option<Property> {
+        let _ = name;
+        None
+    }
+
+    /// Returns the approximate number of bytes of memory used by the engine.
+    ///
+    /// Writes are delayed or rejected when the total usage of all engines
+    /// exceeds the thresholds in options. The default implementation returns
+    /// 0, which never stalls writes.
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    /// Returns true if the engine releases memory in the background, such as
+    /// by flushing memtables.
+    ///
+    /// Writes are only rejected if all engines release memory, otherwise the
+    /// usage never drops and the database stops taking writes for good. The
+    /// default implementation returns false.
+    fn releases_memory(&self) -> bool {
+        false
+    }
+}
+
+/// A bucket in the engine.
+pub trait Bucket {
+    type Handle: BucketHandle;
+
+    /// Opens a bucket with the given handle.
+    fn open(handle: Arc<Self::Handle>) -> Self;
+
+    /// Returns the handle of the bucket.
+    fn handle(&self) -> &Self::Handle;
+}
+
+/// A handle to an opened bucket.
+pub trait BucketHandle: Any + Send + Sync + 'static {
+    /// Returns the id of the bucket.
+    fn id(&self) -> u64;
+
+    /// Returns the engine id of the bucket.
+    fn engine_id(&self) -> u64;
+}
+
+/// A reader associated with a bucket.
+pub trait Reader<'a> {
+    /// The handle of the engine to read from.
+    type Engine: EngineHandle;
+
+    /// Creates a reader for bucket `id` in `engine`.
+    ///
+    /// The reader should only see writes with LSNs up to `lsn`, which are
+    /// the writes published when the reader is created.
+    fn new(engine: &'a Self::Engine, id: u64, lsn: u64) -> Self;
+}
+
+/// A writer associated with a bucket.
+pub trait Writer<'a> {
+    /// Creates a writer for the given bucket.
+    fn new(id: u64, buf: &'a mut Vec<u8>) -> Self;
+}
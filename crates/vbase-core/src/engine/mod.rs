@@ -1,4 +1,9 @@
-pub mod internal;
+use std::fmt;
+
+pub mod ext;
+pub mod scheduler;
+#[cfg(feature = "test")]
+pub mod testkit;
 
 /// A database engine.
 #[allow(private_bounds)]
@@ -13,22 +18,38 @@ pub trait Bucket: sealed::Bucket {
     type Writer<'a>: sealed::Writer<'a>;
 }
 
+/// The value of a database property.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Property {
+    Int(u64),
+    Str(String),
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(x) => x.fmt(f),
+            Self::Str(x) => x.fmt(f),
+        }
+    }
+}
+
 mod sealed {
-    use super::internal;
+    use super::ext;
 
-    pub(super) trait Engine: internal::Engine {}
+    pub(super) trait Engine: ext::Engine {}
 
-    impl<T: internal::Engine> Engine for T {}
+    impl<T: ext::Engine> Engine for T {}
 
-    pub(super) trait Bucket: internal::Bucket {}
+    pub(super) trait Bucket: ext::Bucket {}
 
-    impl<T: internal::Bucket> Bucket for T {}
+    impl<T: ext::Bucket> Bucket for T {}
 
-    pub(super) trait Reader<'a>: internal::Reader<'a> {}
+    pub(super) trait Reader<'a>: ext::Reader<'a> {}
 
-    impl<'a, T: internal::Reader<'a>> Reader<'a> for T {}
+    impl<'a, T: ext::Reader<'a>> Reader<'a> for T {}
 
-    pub(super) trait Writer<'a>: internal::Writer<'a> {}
+    pub(super) trait Writer<'a>: ext::Writer<'a> {}
 
-    impl<'a, T: internal::Writer<'a>> Writer<'a> for T {}
+    impl<'a, T: ext::Writer<'a>> Writer<'a> for T {}
 }
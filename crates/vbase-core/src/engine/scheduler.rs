@@ -0,0 +1,240 @@
+//! Background jobs of engines.
+//!
+//! A database runs a pool of background threads that is shared by all of its
+//! engines. Each engine gets a [`Scheduler`] before it is recovered, and
+//! submits jobs such as flushes and compactions to it. Jobs are split into
+//! two priorities, which run in separate threads, so that a long compaction
+//! does not delay a flush that writes are waiting for.
+//!
+//! The pool is shut down when the database is closed. Running jobs are waited
+//! for, but queued jobs are dropped without running, so engines must be able
+//! to recover from work that was scheduled but never done.
+
+use std::collections::VecDeque;
+use std::panic::AssertUnwindSafe;
+
+use log::info;
+use log::warn;
+use vbase_util::sync::Arc;
+use vbase_util::sync::Condvar;
+use vbase_util::sync::Mutex;
+use vbase_util::thread;
+use vbase_util::thread::JoinHandle;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// The priority of a background job.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Priority {
+    /// Jobs that other work waits for, such as flushes.
+    High,
+    /// Jobs that can wait, such as compactions.
+    Low,
+}
+
+/// A handle to submit jobs to the background threads of a database.
+#[derive(Clone)]
+pub struct Scheduler {
+    queues: Arc<[Queue; 2]>,
+}
+
+impl Scheduler {
+    /// Submits a job with the given priority.
+    ///
+    /// Jobs with the same priority start in submission order. Returns false
+    /// and drops the job if the database is closed.
+    pub fn submit<F>(&self, priority: Priority, job: F) -> bool
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.queues[priority as usize].push(Box::new(job))
+    }
+}
+
+/// A queue of jobs with the same priority.
+#[derive(Default)]
+struct Queue {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    jobs: VecDeque<Job>,
+    is_closed: bool,
+}
+
+impl Queue {
+    fn push(&self, job: Job) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.is_closed {
+            return false;
+        }
+        state.jobs.push_back(job);
+        self.cond.notify_one();
+        true
+    }
+
+    /// Pops a job, blocking until one is available.
+    ///
+    /// Returns None if the queue is closed.
+    fn pop(&self) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.is_closed {
+                return None;
+            }
+            if let Some(job) = state.jobs.pop_front() {
+                return Some(job);
+            }
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+
+    /// Closes the queue and returns the pending jobs.
+    fn close(&self) -> VecDeque<Job> {
+        let mut state = self.state.lock().unwrap();
+        state.is_closed = true;
+        self.cond.notify_all();
+        std::mem::take(&mut state.jobs)
+    }
+}
+
+/// A pool of background threads for both priorities.
+pub(crate) struct ThreadPool {
+    scheduler: Scheduler,
+    threads: Vec<JoinHandle<()>>,
+    /// The process that spawned the threads.
+    pid: u32,
+}
+
+impl ThreadPool {
+    /// Spawns `high` threads for high-priority jobs and `low` threads for
+    /// low-priority jobs.
+    pub(crate) fn new(high: usize, low: usize) -> Self {
+        let scheduler = Scheduler {
+            queues: Arc::new(Default::default()),
+        };
+        let mut threads = Vec::with_capacity(high + low);
+        for (priority, count) in [(Priority::High, high), (Priority::Low, low)] {
+            for _ in 0..count {
+                let queues = scheduler.queues.clone();
+                threads.push(thread::spawn(move || {
+                    let queue = &queues[priority as usize];
+                    while let Some(job) = queue.pop() {
+                        // Keep the thread alive if a job panics.
+                        if std::panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                            warn!("{priority:?} priority job panicked");
+                        }
+                    }
+                }));
+            }
+        }
+        Self {
+            scheduler,
+            threads,
+            pid: std::process::id(),
+        }
+    }
+
+    pub(crate) fn scheduler(&self) -> Scheduler {
+        self.scheduler.clone()
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Threads are not copied to a forked process, where they can not be
+        // joined and may have left the queues locked, so leave them alone.
+        if std::process::id() != self.pid {
+            std::mem::forget(std::mem::take(&mut self.threads));
+            return;
+        }
+        // Drop pending jobs outside of the locks, since they may own anything.
+        let pending = self
+            .scheduler
+            .queues
+            .iter()
+            .map(|q| q.close().len())
+            .sum::<usize>();
+        if pending > 0 {
+            info!("drop {pending} pending background jobs");
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test() {
+        let pool = ThreadPool::new(1, 1);
+        let scheduler = pool.scheduler();
+        let (tx, rx) = mpsc::channel();
+
+        // Block the low-priority thread, high-priority jobs still run.
+        let (block_tx, block_rx) = mpsc::channel::<()>();
+        let low = tx.clone();
+        assert!(scheduler.submit(Priority::Low, move || {
+            block_rx.recv().unwrap();
+            low.send("low").unwrap();
+        }));
+        for name in ["high 1", "high 2"] {
+            let tx = tx.clone();
+            assert!(scheduler.submit(Priority::High, move || tx.send(name).unwrap()));
+        }
+        assert_eq!(rx.recv().unwrap(), "high 1");
+        assert_eq!(rx.recv().unwrap(), "high 2");
+
+        // A panicking job does not stop its thread.
+        assert!(scheduler.submit(Priority::High, || panic!("job")));
+        let high = tx.clone();
+        assert!(scheduler.submit(Priority::High, move || high.send("high 3").unwrap()));
+        assert_eq!(rx.recv().unwrap(), "high 3");
+
+        // Queued jobs are dropped on shutdown, running ones are waited for.
+        let queued = tx.clone();
+        assert!(scheduler.submit(Priority::Low, move || queued.send("queued").unwrap()));
+        block_tx.send(()).unwrap();
+        drop(pool);
+        drop(tx);
+        let rest = rx.iter().collect::<Vec<_>>();
+        assert!(rest == ["low"] || rest == ["low", "queued"], "{rest:?}");
+
+        // Jobs are rejected after shutdown.
+        assert!(!scheduler.submit(Priority::High, || {}));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fork() {
+        let pool = ThreadPool::new(1, 1);
+        // SAFETY: the child only drops the pool and exits.
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            // The child has none of the threads to join.
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| drop(pool)));
+            // SAFETY: exit without running destructors of the parent.
+            unsafe { libc::_exit(result.is_err() as i32) };
+        }
+        let mut status = 0;
+        // SAFETY: `pid` is a child of this process.
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+
+        // The parent still runs jobs.
+        let (tx, rx) = mpsc::channel();
+        assert!(
+            pool.scheduler()
+                .submit(Priority::High, move || tx.send(()).unwrap())
+        );
+        rx.recv().unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! Conformance tests for engine implementations.
+//!
+//! An engine can call [`run`] in its own tests to check that it follows the
+//! contract of [`EngineHandle`]. Each test opens the engine in a fresh test
+//! environment and panics on violations.
+//!
+//! The kit does not know the formats of batches and readers, so engines
+//! implement [`TestEngine`] to put and get values in the tests that check
+//! data.
+
+use vbase_env::boxed::Env;
+
+use crate::Error;
+use crate::Result;
+use crate::engine::ext::Engine;
+use crate::engine::ext::EngineHandle;
+
+const PATH: &str = "testkit";
+const ID: u64 = 1;
+
+/// An engine that can be checked by the tests that write data.
+pub trait TestEngine: Engine {
+    /// Appends a batch to `buf` that puts `value` for `key` in bucket `id`.
+    fn put(id: u64, key: &[u8], value: &[u8], buf: &mut Vec<u8>);
+
+    /// Returns the value for `key` in bucket `id` visible at `lsn`.
+    fn get(engine: &Self::Handle, id: u64, key: &[u8], lsn: u64) -> Result<Option<Vec<u8>>>;
+}
+
+/// Runs all conformance tests against engine `E`.
+pub fn run<E: TestEngine>() -> Result<()> {
+    test_bucket_lifecycle::<E>()?;
+    test_reopen::<E>()?;
+    test_lsn_monotonic::<E>()?;
+    test_write_after_delete::<E>()?;
+    test_replay::<E>()?;
+    Ok(())
+}
+
+/// Checks that buckets can be created, opened and deleted.
+pub fn test_bucket_lifecycle<E: Engine>() -> Result<()> {
+    let env = Env::test()?;
+    let engine = E::open(ID, env.create_dir(PATH)?)?;
+    assert_eq!(engine.id(), ID);
+    assert_eq!(engine.name(), E::NAME);
+
+    match engine.bucket("a") {
+        Err(Error::NotExist(_)) => {}
+        x => panic!("unexpected result: {:?}", x.map(|_| ())),
+    }
+    let a = engine.create_bucket("a")?;
+    assert_eq!(a.engine_id(), ID);
+    match engine.create_bucket("a") {
+        Err(Error::Exists(_)) => {}
+        x => panic!("unexpected result: {:?}", x.map(|_| ())),
+    }
+    assert_eq!(engine.bucket("a")?.id(), a.id());
+    let b = engine.create_bucket("b")?;
+    assert_ne!(a.id(), b.id());
+
+    engine.delete_bucket("a")?;
+    match engine.delete_bucket("a") {
+        Err(Error::NotExist(_)) => {}
+        x => panic!("unexpected result: {x:?}"),
+    }
+    match engine.bucket("a") {
+        Err(Error::NotExist(_)) => {}
+        x => panic!("unexpected result: {:?}", x.map(|_| ())),
+    }
+    assert_eq!(engine.bucket("b")?.id(), b.id());
+
+    // Ids of deleted buckets are never reused.
+    let c = engine.create_bucket("a")?;
+    assert_ne!(c.id(), a.id());
+    assert_ne!(c.id(), b.id());
+    Ok(())
+}
+
+/// Checks that the engine recovers the same state every time it is reopened.
+pub fn test_reopen<E: Engine>() -> Result<()> {
+    let env = Env::test()?;
+    let (a, b, last_lsn) = {
+        let engine = E::open(ID, env.create_dir(PATH)?)?;
+        let a = engine.create_bucket("a")?.id();
+        let b = engine.create_bucket("b")?.id();
+        engine.delete_bucket("b")?;
+        (a, b, engine.last_lsn())
+    };
+    for _ in 0..3 {
+        let engine = E::open(ID, env.open_dir(PATH)?)?;
+        assert_eq!(engine.last_lsn(), last_lsn);
+        assert_eq!(engine.bucket("a")?.id(), a);
+        match engine.bucket("b") {
+            Err(Error::NotExist(_)) => {}
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+    }
+
+    // Ids of deleted buckets are never reused after reopening.
+    let engine = E::open(ID, env.open_dir(PATH)?)?;
+    let c = engine.create_bucket("c")?.id();
+    assert_ne!(c, a);
+    assert_ne!(c, b);
+    Ok(())
+}
+
+/// Checks that the last LSN covers every write and never goes back.
+pub fn test_lsn_monotonic<E: TestEngine>() -> Result<()> {
+    let env = Env::test()?;
+    let last_lsn = {
+        let engine = E::open(ID, env.create_dir(PATH)?)?;
+        let id = engine.create_bucket("a")?.id();
+        let start = engine.last_lsn();
+        let mut buf = Vec::new();
+        // Writes are applied in parallel, so LSNs may arrive out of order.
+        let mut max_lsn = start;
+        for lsn in [start + 2, start + 1, start + 4, start + 3] {
+            buf.clear();
+            E::put(id, b"k", &lsn.to_be_bytes(), &mut buf);
+            engine.write(lsn, &buf);
+            max_lsn = max_lsn.max(lsn);
+            assert_eq!(engine.last_lsn(), max_lsn);
+        }
+        max_lsn
+    };
+
+    // A reopened engine may lag behind, since the database replays the writes
+    // after its last LSN, but it must not claim writes it does not have.
+    for _ in 0..3 {
+        let engine = E::open(ID, env.open_dir(PATH)?)?;
+        assert!(engine.last_lsn() <= last_lsn);
+    }
+    Ok(())
+}
+
+/// Checks that nothing written to a deleted bucket is visible, including
+/// in a new bucket with the same name.
+pub fn test_write_after_delete<E: TestEngine>() -> Result<()> {
+    let env = Env::test()?;
+    let mut buf = Vec::new();
+    let (old, new, lsn) = {
+        let engine = E::open(ID, env.create_dir(PATH)?)?;
+        let old = engine.create_bucket("a")?.id();
+        let mut lsn = engine.last_lsn() + 1;
+        E::put(old, b"k", b"1", &mut buf);
+        engine.write(lsn, &buf);
+        assert_eq!(E::get(&engine, old, b"k", lsn)?, Some(b"1".to_vec()));
+
+        engine.delete_bucket("a")?;
+        assert_eq!(E::get(&engine, old, b"k", lsn)?, None);
+        let new = engine.create_bucket("a")?.id();
+        assert_eq!(E::get(&engine, new, b"k", lsn)?, None);
+
+        // Writes with the id of the deleted bucket are ignored.
+        lsn += 1;
+        engine.write(lsn, &buf);
+        assert_eq!(E::get(&engine, old, b"k", lsn)?, None);
+        assert_eq!(E::get(&engine, new, b"k", lsn)?, None);
+        (old, new, lsn)
+    };
+
+    // Replaying the same writes after reopening is ignored too.
+    let engine = E::open(ID, env.open_dir(PATH)?)?;
+    assert_eq!(engine.bucket("a")?.id(), new);
+    for lsn in engine.last_lsn() + 1..=lsn {
+        engine.replay(lsn, engine.batch_version(), &buf);
+    }
+    assert_eq!(E::get(&engine, old, b"k", lsn)?, None);
+    assert_eq!(E::get(&engine, new, b"k", lsn)?, None);
+    Ok(())
+}
+
+/// Checks that replaying the journal after every reopen recovers the same
+/// data.
+pub fn test_replay<E: TestEngine>() -> Result<()> {
+    let env = Env::test()?;
+    let mut batches = Vec::new();
+    let id = {
+        let engine = E::open(ID, env.create_dir(PATH)?)?;
+        let id = engine.create_bucket("a")?.id();
+        let start = engine.last_lsn();
+        for i in 1..=3u8 {
+            let mut buf = Vec::new();
+            E::put(id, &[i], &[i], &mut buf);
+            E::put(id, b"k", &[i], &mut buf);
+            let lsn = start + i as u64;
+            engine.write(lsn, &buf);
+            batches.push((lsn, buf));
+        }
+        id
+    };
+    let last_lsn = batches.last().unwrap().0;
+
+    for _ in 0..3 {
+        let engine = E::open(ID, env.open_dir(PATH)?)?;
+        // Replay like the database does, skipping the writes that the engine
+        // already has.
+        let from = engine.last_lsn();
+        for (lsn, batch) in batches.iter().filter(|(lsn, _)| *lsn > from) {
+            engine.replay(*lsn, engine.batch_version(), batch);
+        }
+        assert_eq!(engine.last_lsn(), last_lsn);
+        for i in 1..=3u8 {
+            assert_eq!(E::get(&engine, id, &[i], last_lsn)?, Some(vec![i]));
+        }
+        assert_eq!(E::get(&engine, id, b"k", last_lsn)?, Some(vec![3]));
+    }
+    Ok(())
+}
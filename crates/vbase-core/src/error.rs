@@ -18,6 +18,10 @@ pub enum Error {
     NotExist(String),
     #[error("{0}")]
     InvalidArgument(String),
+    #[error("{0} is poisoned: {1}")]
+    Poisoned(String, String),
+    #[error("{0}")]
+    Busy(String),
 }
 
 #[doc(hidden)]
@@ -27,6 +31,7 @@ impl From<vbase_file::Error> for Error {
         match e {
             E::Io(e) => Error::Io(e),
             E::Corrupted { name, message } => Error::Corrupted { name, message },
+            E::InvalidArgument(message) => Error::InvalidArgument(message),
         }
     }
 }
@@ -1,90 +1,310 @@
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::io::ErrorKind;
+use std::time::Duration;
+use std::time::SystemTime;
 
+use log::info;
+use log::warn;
+use vbase_env::SequentialFileWriter as _;
 use vbase_env::boxed::Dir;
 use vbase_env::boxed::LockedFile;
+use vbase_env::boxed::TempFile;
+use vbase_util::clock::Clock;
+use vbase_util::failpoint;
+use vbase_util::rand::random_u64;
+use vbase_util::rate_limiter::RateLimiter;
+use vbase_util::sync::Arc;
+use vbase_util::sync::Mutex;
 
 use crate::Error;
 use crate::Result;
+use crate::engine::ext::EngineHandle;
+use crate::engine::scheduler::Priority;
+use crate::engine::scheduler::Scheduler;
 use crate::error::Corrupted;
+use crate::info_log::InfoLog;
+use crate::info_log::event;
 use crate::journal::Journal;
 use crate::journal::JournalWriter;
 use crate::manifest::Desc;
+use crate::manifest::FORMAT_VERSION;
+use crate::options::ArchiveSink;
+use crate::options::Options;
+
+/// Purges the first trash file in a job, which submits another job for the
+/// rest.
+fn purge_trash_files(
+    dir: Arc<Dir>,
+    mut files: VecDeque<String>,
+    limiter: Arc<RateLimiter>,
+    scheduler: Scheduler,
+) {
+    let Some(name) = files.pop_front() else {
+        return;
+    };
+    let next = scheduler.clone();
+    scheduler.submit(Priority::Low, move || {
+        limiter.request(1);
+        info!("purge trash file {name}");
+        if let Err(e) = dir.delete_file(&name) {
+            warn!("failed to purge trash file {name}: {e}");
+        }
+        purge_trash_files(dir, files, limiter, next);
+    });
+}
 
 #[derive(Default)]
 pub(crate) struct FileSet {
     pub(crate) engines: BTreeSet<u64>,
+    pub(crate) archived_engines: BTreeSet<u64>,
     pub(crate) journals: BTreeSet<u64>,
+    pub(crate) options_files: BTreeSet<u64>,
+    pub(crate) temp_files: BTreeSet<String>,
+    /// Trash files with the time they were deleted, in seconds since the
+    /// Unix epoch.
+    pub(crate) trash_files: BTreeMap<String, u64>,
 }
 
 pub(crate) struct RootDir {
-    dir: Dir,
+    /// The root directory, shared with background jobs such as purging trash.
+    dir: Arc<Dir>,
     #[allow(dead_code)]
     lock: LockedFile,
+    /// The fencing token recorded next to the lock file.
+    ///
+    /// The token changes every time the lock is acquired, so a process whose
+    /// lock has been stolen can detect it before updating the database.
+    token: u64,
+    /// The directory of journal files.
+    journal_dir: Option<Arc<Dir>>,
+    /// The parent directories of engines, indexed by engine name.
+    engine_dirs: HashMap<String, Dir>,
+    /// The queue size of journal writes in the background.
+    journal_write_queue: usize,
+    /// The sink to archive journal files.
+    archive_sink: Option<Arc<dyn ArchiveSink>>,
+    /// The clock to timestamp trash files.
+    clock: Arc<dyn Clock>,
+    /// How long deleted files are kept in trash.
+    trash_retention: Option<Duration>,
+    /// The maximum number of trash files purged per second.
+    trash_purge_rate: u64,
+    /// The log of database events, if enabled.
+    info_log: Option<InfoLog>,
+    /// The current manifest.
+    ///
+    /// All manifest updates are serialized by this lock, so that concurrent
+    /// updates never overwrite each other.
+    manifest: Mutex<Desc>,
 }
 
 impl RootDir {
     const LOCK: &str = "LOCK";
-    const TEMP: &str = "TEMP";
+    const LOCK_INFO: &str = "LOCK.info";
     const MANIFEST: &str = "MANIFEST";
+    const IDENTITY: &str = "IDENTITY";
 
-    pub(crate) fn lock(dir: Dir) -> Result<Self> {
-        let lock = match dir.lock_file(Self::LOCK) {
-            Ok(x) => x,
-            Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                return Err(Error::Locked(dir.path().into()));
+    pub(crate) fn lock(path: &str, dir: Dir, options: &Options) -> Result<Self> {
+        let (lock, token) = LockInfo::acquire(&dir, options.steal_stale_lock)?;
+        let info_log = match options.info_log_size {
+            0 => None,
+            size => Some(InfoLog::open(
+                &dir,
+                options.clock.clone(),
+                size,
+                options.info_log_files,
+            )?),
+        };
+        let journal_dir = match (&options.journal_env, &options.paths.journal) {
+            (None, None) => None,
+            (env, journal_path) => {
+                let env = env.as_ref().unwrap_or(&options.env);
+                let path = journal_path.as_deref().unwrap_or(path);
+                Some(Arc::new(env.create_dir(path)?))
             }
-            Err(e) => return Err(e.into()),
         };
-        Ok(Self { dir, lock })
+        let mut engine_dirs = HashMap::new();
+        for (name, path) in &options.paths.engines {
+            engine_dirs.insert(name.clone(), options.env.create_dir(path)?);
+        }
+        Ok(Self {
+            dir: Arc::new(dir),
+            lock,
+            token,
+            journal_dir,
+            engine_dirs,
+            journal_write_queue: options.journal_write_queue,
+            archive_sink: options.archive_sink.clone(),
+            clock: options.clock.clone(),
+            trash_retention: options.trash_retention,
+            trash_purge_rate: options.trash_purge_rate,
+            info_log,
+            manifest: Mutex::new(Desc::default()),
+        })
     }
 
     pub(crate) fn path(&self) -> &str {
         self.dir.path()
     }
 
+    /// Writes an event to the info log, if enabled.
+    ///
+    /// Use [`event`] to log it with [`log::info`] as well.
+    pub(crate) fn log_event(&self, message: &str) {
+        if let Some(log) = &self.info_log {
+            log.write(&self.dir, message);
+        }
+    }
+
     pub(crate) fn list(&self) -> Result<FileSet> {
         let mut list = FileSet::default();
-        let names = self.dir.list()?;
-        for name in names.iter().filter_map(|name| Name::parse(name)) {
-            let _ = match name {
-                Name::Engine(id) => list.engines.insert(id),
-                Name::Journal(id) => list.journals.insert(id),
-            };
+        for name in self.dir.list()? {
+            if TempFile::is_temp(&name) {
+                list.temp_files.insert(name);
+            } else if let Some(Name::Options(id)) = Name::parse(&name) {
+                list.options_files.insert(id);
+            }
+        }
+        for dir in self.engine_parents() {
+            for name in dir.list()?.iter().filter_map(|name| Name::parse(name)) {
+                match name {
+                    Name::Engine(id) => list.engines.insert(id),
+                    Name::ArchivedEngine(id) => list.archived_engines.insert(id),
+                    Name::Journal(_) | Name::Options(_) | Name::Trash(..) => false,
+                };
+            }
+        }
+        for name in self
+            .journal_dir()
+            .list()?
+            .iter()
+            .filter_map(|name| Name::parse(name))
+        {
+            match name {
+                Name::Journal(id) => {
+                    list.journals.insert(id);
+                }
+                Name::Trash(name, time) => {
+                    list.trash_files.insert(name, time);
+                }
+                _ => {}
+            }
         }
         Ok(list)
     }
 
-    pub(crate) fn open_engine(&self, id: u64) -> Result<Dir> {
+    pub(crate) fn open_engine(&self, id: u64, engine: &str) -> Result<Dir> {
         let name = Name::engine(id);
-        self.dir.open_dir(&name).map_err(Into::into)
+        self.engine_parent(engine)
+            .open_dir(&name)
+            .map_err(Into::into)
     }
 
-    pub(crate) fn create_engine(&self, id: u64) -> Result<Dir> {
+    pub(crate) fn create_engine(&self, id: u64, engine: &str) -> Result<Dir> {
         let name = Name::engine(id);
-        self.dir.create_dir(&name).map_err(Into::into)
+        self.engine_parent(engine)
+            .create_dir(&name)
+            .map_err(Into::into)
+    }
+
+    /// Renames the directory of an engine to its archive name.
+    pub(crate) fn archive_engine(&self, id: u64, engine: &str) -> Result<()> {
+        let from = Name::engine(id);
+        let to = Name::archived_engine(id);
+        self.engine_parent(engine)
+            .rename_dir(&from, &to)
+            .map_err(Into::into)
+    }
+
+    /// Renames the directory of an archived engine back to its engine name.
+    pub(crate) fn restore_engine(&self, id: u64, engine: &str) -> Result<()> {
+        let from = Name::archived_engine(id);
+        let to = Name::engine(id);
+        self.engine_parent(engine)
+            .rename_dir(&from, &to)
+            .map_err(Into::into)
     }
 
+    /// Deletes an engine from whichever directory contains it.
     pub(crate) fn delete_engine(&self, id: u64) -> Result<()> {
         let name = Name::engine(id);
-        self.dir.delete_dir(&name).map_err(Into::into)
+        for dir in self.engine_parents() {
+            match dir.delete_dir(&name) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
     }
 
     pub(crate) fn open_journal(&self, id: u64) -> Result<Journal> {
         let name = Name::journal(id);
-        let file = self.dir.open_sequential_file(&name)?;
+        let file = self.journal_dir().open_sequential_file(&name)?;
         Ok(Journal::new(file))
     }
 
-    pub(crate) fn create_journal(&self, id: u64) -> Result<JournalWriter> {
+    pub(crate) fn create_journal(&self, id: u64, epoch: u64) -> Result<JournalWriter> {
         let name = Name::journal(id);
-        let file = self.dir.create_sequential_file(&name)?;
-        Ok(JournalWriter::new(file))
+        let mut file = self.journal_dir().create_sequential_file(&name)?;
+        if self.journal_write_queue > 0 {
+            file = file.into_background(self.journal_write_queue)?;
+        }
+        Ok(JournalWriter::new(id, epoch, file))
     }
 
     pub(crate) fn delete_journal(&self, id: u64) -> Result<()> {
         let name = Name::journal(id);
-        self.dir.delete_file(&name).map_err(Into::into)
+        if let Some(sink) = &self.archive_sink {
+            sink.archive_journal(self.journal_dir(), &name)?;
+        }
+        if self.trash_retention.is_some() {
+            let to = Name::trash(&name, self.clock.unix_secs());
+            event!(self, "move {name} to trash {to}");
+            return self
+                .journal_dir()
+                .rename_file(&name, &to)
+                .map_err(Into::into);
+        }
+        self.journal_dir().delete_file(&name).map_err(Into::into)
+    }
+
+    /// Purges trash files that have been kept longer than the retention.
+    ///
+    /// Files are purged in low-priority jobs, one file per job, so that a
+    /// rate-limited purge does not hold up opening or closing the database.
+    /// Files left when the database is closed are purged on the next open.
+    pub(crate) fn purge_trash(&self, scheduler: Scheduler) -> Result<()> {
+        let retention = self.trash_retention.unwrap_or_default().as_secs();
+        let now = self.clock.unix_secs();
+        let files = self
+            .list()?
+            .trash_files
+            .into_iter()
+            .filter(|&(_, time)| time.saturating_add(retention) <= now)
+            .map(|(name, _)| name)
+            .collect::<VecDeque<_>>();
+        if !files.is_empty() {
+            event!(self, "purge {} trash files", files.len());
+        }
+        let limiter = Arc::new(RateLimiter::with_clock(
+            self.trash_purge_rate,
+            self.clock.clone(),
+        ));
+        purge_trash_files(self.journal_dir().clone(), files, limiter, scheduler);
+        Ok(())
+    }
+
+    /// Passes the first `size` bytes of a synchronized journal to the archive
+    /// sink.
+    pub(crate) fn sync_journal(&self, id: u64, size: u64) -> Result<()> {
+        match &self.archive_sink {
+            Some(sink) => sink.sync_journal(self.journal_dir(), &Name::journal(id), size),
+            None => Ok(()),
+        }
     }
 
     pub(crate) fn read_manifest(&self) -> Result<Option<Desc>> {
@@ -97,25 +317,217 @@ impl RootDir {
         }
     }
 
-    pub(crate) fn switch_manifest(&self, desc: &Desc) -> Result<()> {
+    /// Reads the manifest file and makes it the current manifest.
+    pub(crate) fn load_manifest(&self) -> Result<Option<Desc>> {
+        let mut current = self.manifest.lock().unwrap();
+        let desc = self.read_manifest()?;
+        if let Some(desc) = &desc {
+            if desc.format_version > FORMAT_VERSION {
+                return Err(Error::InvalidArgument(format!(
+                    "{} was created by a newer version with format {}, \
+                    but this version only supports format {FORMAT_VERSION}",
+                    self.path(),
+                    desc.format_version,
+                )));
+            }
+            *current = desc.clone();
+        }
+        Ok(desc)
+    }
+
+    /// Checks that the manifest file matches the current manifest.
+    pub(crate) fn verify_manifest(&self) -> Result<()> {
+        let current = self.manifest.lock().unwrap();
+        let Some(desc) = self.read_manifest()? else {
+            return Err(Error::NotExist(format!("manifest in {}", self.path())));
+        };
+        if desc.generation != current.generation {
+            return Self::MANIFEST.corrupted(format!(
+                "generation mismatch (expected {}, got {})",
+                current.generation, desc.generation
+            ));
+        }
+        Ok(())
+    }
+
+    /// Applies `f` to a copy of the current manifest and switches to it.
+    ///
+    /// The current manifest is left untouched if `f` or the switch fails.
+    pub(crate) fn update_manifest<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Desc) -> Result<T>,
+    {
+        let mut current = self.manifest.lock().unwrap();
+        self.check_lock()?;
+        let mut desc = current.clone();
+        let result = f(&mut desc)?;
+        desc.generation = current.generation + 1;
+        desc.format_version = FORMAT_VERSION;
+        self.switch_manifest(&desc)?;
+        *current = desc;
+        Ok(result)
+    }
+
+    /// Updates the identity file with the registered `engines`.
+    ///
+    /// A new identity is created if the file does not exist. Otherwise, the
+    /// id and the creation time are kept. A corrupted file is regenerated,
+    /// with the id and the creation time in it if they can still be read,
+    /// since the file is informational and should not prevent opening.
+    pub(crate) fn update_identity(&self, mut engines: Vec<String>) -> Result<Identity> {
+        let (old, is_valid) = match self.dir.read_file(Self::IDENTITY) {
+            Ok(data) => {
+                let data = String::from_utf8_lossy(&data);
+                match Identity::decode(&data) {
+                    Some(identity) => (Some(identity), true),
+                    None => {
+                        let identity = Identity::salvage(&data, self.clock.unix_secs());
+                        warn!("{} has invalid content {data:?}", Self::IDENTITY);
+                        event!(
+                            self,
+                            "regenerate {} with id {}",
+                            Self::IDENTITY,
+                            identity.id
+                        );
+                        (Some(identity), false)
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (None, false),
+            Err(e) => return Err(e.into()),
+        };
+        engines.sort();
+        let identity = Identity {
+            version: env!("CARGO_PKG_VERSION").into(),
+            engines,
+            ..old
+                .clone()
+                .unwrap_or_else(|| Identity::new(self.clock.unix_secs()))
+        };
+        if !is_valid || old.is_some_and(|old| old != identity) {
+            let mut file = self.dir.create_temp_file(Self::IDENTITY)?;
+            file.write_exact(identity.encode().as_bytes())?;
+            file.persist(Self::IDENTITY)?;
+        }
+        Ok(identity)
+    }
+
+    /// Writes the options file `id` and deletes older ones, except the
+    /// previous one.
+    pub(crate) fn write_options(&self, id: u64, text: &str) -> Result<()> {
+        let name = Name::options(id);
+        let mut file = self.dir.create_temp_file(&name)?;
+        file.write_exact(text.as_bytes())?;
+        file.persist(&name)?;
+        let list = self.list()?.options_files;
+        for old in list.range(..id).rev().skip(1) {
+            self.dir.delete_file(&Name::options(*old))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn delete_temp_file(&self, name: &str) -> Result<()> {
+        self.dir.delete_file(name).map_err(Into::into)
+    }
+
+    /// Passes the manifest file to `copy` and returns the manifest.
+    pub(crate) fn backup_manifest(
+        &self,
+        copy: &mut dyn FnMut(&str, &[u8]) -> Result<()>,
+    ) -> Result<Desc> {
+        let current = self.manifest.lock().unwrap();
+        copy(Self::MANIFEST, &current.encode_with_checksum())?;
+        Ok(current.clone())
+    }
+
+    /// Passes the files of engine `id` to `copy`, under the directory of the
+    /// engine.
+    pub(crate) fn backup_engine(
+        &self,
+        id: u64,
+        engine: &dyn EngineHandle,
+        copy: &mut dyn FnMut(&str, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let dir = Name::engine(id);
+        engine.backup_files(&mut |name, data| copy(&format!("{dir}/{name}"), data))
+    }
+
+    /// Passes journals up to journal `id` to `copy`, where journal `id` is
+    /// limited to its first `size` bytes.
+    pub(crate) fn backup_journals(
+        &self,
+        id: u64,
+        size: u64,
+        copy: &mut dyn FnMut(&str, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        for journal in self.list()?.journals.range(..=id) {
+            let name = Name::journal(*journal);
+            let mut data = self.journal_dir().read_file(&name)?;
+            if *journal == id {
+                data.truncate(size as usize);
+            }
+            copy(&name, &data)?;
+        }
+        Ok(())
+    }
+}
+
+impl RootDir {
+    /// Checks that the lock has not been stolen by another process.
+    fn check_lock(&self) -> Result<()> {
+        let info = LockInfo::read(&self.dir)?;
+        if info.is_none_or(|info| info.token != self.token) {
+            return Err(Error::Locked(self.dir.path().into()));
+        }
+        Ok(())
+    }
+
+    fn switch_manifest(&self, desc: &Desc) -> Result<()> {
         let data = desc.encode_with_checksum();
-        self.dir.write_file(Self::TEMP, &data)?;
-        self.dir.rename_file(Self::TEMP, Self::MANIFEST)?;
+        let mut file = self.dir.create_temp_file(Self::MANIFEST)?;
+        file.write_exact(&data)?;
+        failpoint::check("core::manifest::before_persist")?;
+        file.persist(Self::MANIFEST)?;
         Ok(())
     }
+
+    fn journal_dir(&self) -> &Arc<Dir> {
+        self.journal_dir.as_ref().unwrap_or(&self.dir)
+    }
+
+    fn engine_parent(&self, engine: &str) -> &Dir {
+        self.engine_dirs.get(engine).unwrap_or(&self.dir)
+    }
+
+    /// Returns all directories that may contain engines.
+    fn engine_parents(&self) -> impl Iterator<Item = &Dir> {
+        std::iter::once(self.dir.as_ref()).chain(self.engine_dirs.values())
+    }
 }
 
 enum Name {
     Engine(u64),
+    ArchivedEngine(u64),
     Journal(u64),
+    Options(u64),
+    Trash(String, u64),
 }
 
 impl Name {
+    const TRASH_SUFFIX: &str = ".trash";
+
     fn parse(name: &str) -> Option<Self> {
-        if let Some(suffix) = name.strip_prefix("engine-") {
+        if let Some(prefix) = name.strip_suffix(Self::TRASH_SUFFIX) {
+            let (_, time) = prefix.rsplit_once('.')?;
+            time.parse().ok().map(|time| Self::Trash(name.into(), time))
+        } else if let Some(suffix) = name.strip_prefix("engine-") {
             suffix.parse().ok().map(Self::Engine)
+        } else if let Some(suffix) = name.strip_prefix("archived-engine-") {
+            suffix.parse().ok().map(Self::ArchivedEngine)
         } else if let Some(suffix) = name.strip_prefix("journal-") {
             suffix.parse().ok().map(Self::Journal)
+        } else if let Some(suffix) = name.strip_prefix("OPTIONS-") {
+            suffix.parse().ok().map(Self::Options)
         } else {
             None
         }
@@ -125,7 +537,218 @@ impl Name {
         format!("engine-{id}")
     }
 
+    fn archived_engine(id: u64) -> String {
+        format!("archived-engine-{id}")
+    }
+
     fn journal(id: u64) -> String {
         format!("journal-{id}")
     }
+
+    fn options(id: u64) -> String {
+        format!("OPTIONS-{id}")
+    }
+
+    fn trash(name: &str, time: u64) -> String {
+        format!("{name}.{time}{}", Self::TRASH_SUFFIX)
+    }
+}
+
+/// The owner of the lock file.
+///
+/// This is kept in a separate file next to the lock file, since the lock may
+/// be mandatory on some platforms, such as Windows, where the locked file can
+/// not be read or written through other handles.
+struct LockInfo {
+    pid: u32,
+    token: u64,
+}
+
+impl LockInfo {
+    /// Acquires the lock and returns the new fencing token.
+    fn acquire(dir: &Dir, steal: bool) -> Result<(LockedFile, u64)> {
+        let (lock, prev) = match dir.lock_file(RootDir::LOCK) {
+            Ok(lock) => (lock, Self::read(dir)?),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                let prev = match Self::read(dir)? {
+                    Some(info) if steal && !is_process_alive(info.pid) => info,
+                    _ => return Err(Error::Locked(dir.path().into())),
+                };
+                // Break the lock by replacing the lock file. The lock file of
+                // the dead process is no longer visible to others.
+                info!("steal lock from dead process {}", prev.pid);
+                dir.delete_file(RootDir::LOCK)?;
+                let lock = dir.lock_file(RootDir::LOCK).map_err(|e| match e.kind() {
+                    ErrorKind::WouldBlock => Error::Locked(dir.path().into()),
+                    _ => e.into(),
+                })?;
+                (lock, Some(prev))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let info = Self {
+            pid: std::process::id(),
+            token: prev.map_or(1, |info| info.token + 1),
+        };
+        let mut file = dir.create_temp_file(RootDir::LOCK_INFO)?;
+        file.write_exact(info.encode().as_bytes())?;
+        file.persist(RootDir::LOCK_INFO)?;
+        Ok((lock, info.token))
+    }
+
+    /// Reads the lock info file.
+    ///
+    /// Returns `None` if the file does not exist or is empty.
+    fn read(dir: &Dir) -> Result<Option<Self>> {
+        let data = match dir.read_file(RootDir::LOCK_INFO) {
+            Ok(x) => x,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let data = String::from_utf8_lossy(&data);
+        match Self::decode(&data) {
+            Some(info) => Ok(Some(info)),
+            None => RootDir::LOCK_INFO.corrupted(format!("invalid content {data:?}")),
+        }
+    }
+
+    fn encode(&self) -> String {
+        format!("{} {}\n", self.pid, self.token)
+    }
+
+    fn decode(data: &str) -> Option<Self> {
+        let (pid, token) = data.trim_end().split_once(' ')?;
+        Some(Self {
+            pid: pid.parse().ok()?,
+            token: token.parse().ok()?,
+        })
+    }
+}
+
+/// The identity of a database.
+///
+/// This is stored in the `IDENTITY` file of the database directory in a
+/// human-readable format. The id and the creation time never change once
+/// the database is created, while the version and the engines are updated
+/// each time the database is opened. A database restored from a backup gets
+/// a new identity.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Identity {
+    id: String,
+    created: u64,
+    version: String,
+    engines: Vec<String>,
+}
+
+impl Identity {
+    /// Returns the unique id of the database in the UUID format.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the time when the database was created.
+    pub fn created(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.created)
+    }
+
+    /// Returns the library version that last opened the database.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Returns the names of the engines registered when the database was
+    /// last opened, in order.
+    pub fn engines(&self) -> &[String] {
+        &self.engines
+    }
+}
+
+impl Identity {
+    /// Creates an identity with a random id.
+    fn new(created: u64) -> Self {
+        // A version 4 UUID.
+        let hi = random_u64() & !0xF000 | 0x4000;
+        let lo = random_u64() & !(0b11 << 62) | (0b10 << 62);
+        let id = format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            hi >> 32,
+            (hi >> 16) & 0xFFFF,
+            hi & 0xFFFF,
+            lo >> 48,
+            lo & 0xFFFF_FFFF_FFFF,
+        );
+        Self {
+            id,
+            created,
+            version: String::new(),
+            engines: Vec::new(),
+        }
+    }
+
+    fn encode(&self) -> String {
+        format!(
+            "id = {}\ncreated = {}\nversion = {}\nengines = {}\n",
+            self.id,
+            self.created,
+            self.version,
+            self.engines.join(","),
+        )
+    }
+
+    /// Reads the id and the creation time from a corrupted identity, or
+    /// creates new ones for those that can not be read.
+    fn salvage(data: &str, now: u64) -> Self {
+        let mut identity = Self::new(now);
+        for (key, value) in data.lines().filter_map(|line| line.split_once('=')) {
+            let value = value.trim();
+            match key.trim() {
+                "id" if value.len() == 36
+                    && value.chars().all(|c| c == '-' || c.is_ascii_hexdigit()) =>
+                {
+                    identity.id = value.into();
+                }
+                "created" => {
+                    if let Ok(created) = value.parse() {
+                        identity.created = created;
+                    }
+                }
+                _ => {}
+            }
+        }
+        identity
+    }
+
+    /// Decodes an identity, ignoring unknown keys.
+    fn decode(data: &str) -> Option<Self> {
+        let mut fields = HashMap::new();
+        for line in data.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = line.split_once('=')?;
+            fields.insert(key.trim(), value.trim());
+        }
+        let engines = fields.get("engines").copied().unwrap_or_default();
+        Some(Self {
+            id: fields.get("id")?.to_string(),
+            created: fields.get("created")?.parse().ok()?,
+            version: fields.get("version").copied().unwrap_or_default().into(),
+            engines: engines
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(Into::into)
+                .collect(),
+        })
+    }
+}
+
+/// Returns true if the process `pid` is alive.
+///
+/// This conservatively returns true if it can not be determined.
+fn is_process_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        std::path::Path::new(&format!("/proc/{pid}")).exists()
+    } else {
+        true
+    }
 }
@@ -0,0 +1,108 @@
+//! A rolling log of database events in the database directory.
+//!
+//! Events are written to the `LOG` file, each on a line with the time in
+//! seconds since the Unix epoch. Once the file reaches the maximum size, or
+//! when the database is opened again, it is renamed to `LOG.{n}` with an
+//! increasing `n`, and only the last few of them are kept.
+//!
+//! The log is independent of the [`log`] backend of the application, so the
+//! history of a database stays with its files. Writing the log is best
+//! effort, failures are reported through [`log`] and otherwise ignored.
+
+use std::time::UNIX_EPOCH;
+
+use log::warn;
+use vbase_env::SequentialFileWriter as _;
+use vbase_env::boxed::Dir;
+use vbase_env::boxed::SequentialFileWriter;
+use vbase_util::clock::Clock;
+use vbase_util::sync::Arc;
+use vbase_util::sync::Mutex;
+
+use crate::Result;
+
+/// Logs an event with [`log::info`] and writes it to the info log of a
+/// [`crate::file::RootDir`].
+macro_rules! event {
+    ($root:expr, $($arg:tt)+) => {{
+        let message = format!($($arg)+);
+        log::info!("{message}");
+        $root.log_event(&message);
+    }};
+}
+pub(crate) use event;
+
+/// The info log of a database directory.
+///
+/// The directory is passed to each call, since it is owned by the caller.
+pub(crate) struct InfoLog {
+    clock: Arc<dyn Clock>,
+    max_size: usize,
+    max_old_files: usize,
+    file: Mutex<SequentialFileWriter>,
+}
+
+impl InfoLog {
+    const NAME: &str = "LOG";
+
+    /// Opens the log in `dir`, rolling the file of the previous open.
+    pub(crate) fn open(
+        dir: &Dir,
+        clock: Arc<dyn Clock>,
+        max_size: usize,
+        max_old_files: usize,
+    ) -> Result<Self> {
+        if dir.list()?.iter().any(|name| name == Self::NAME) {
+            Self::roll(dir, max_old_files)?;
+        }
+        let file = dir.create_sequential_file(Self::NAME)?;
+        Ok(Self {
+            clock,
+            max_size,
+            max_old_files,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Writes an event to the log in `dir`.
+    pub(crate) fn write(&self, dir: &Dir, message: &str) {
+        let time = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let line = format!("{}.{:03} {message}\n", time.as_secs(), time.subsec_millis());
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = self.write_line(dir, &mut file, &line) {
+            warn!("failed to write info log in {}: {e}", dir.path());
+        }
+    }
+
+    fn write_line(&self, dir: &Dir, file: &mut SequentialFileWriter, line: &str) -> Result<()> {
+        if file.offset() > 0 && file.offset() + line.len() as u64 > self.max_size as u64 {
+            Self::roll(dir, self.max_old_files)?;
+            *file = dir.create_sequential_file(Self::NAME)?;
+        }
+        file.write_exact(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Renames the current file to the next old file, and deletes old files
+    /// beyond `max_old_files`.
+    fn roll(dir: &Dir, max_old_files: usize) -> Result<()> {
+        let mut old = dir
+            .list()?
+            .iter()
+            .filter_map(|name| name.strip_prefix("LOG.")?.parse().ok())
+            .collect::<Vec<u64>>();
+        old.sort_unstable();
+        let next = old.last().map_or(1, |n| n + 1);
+        dir.rename_file(Self::NAME, &format!("{}.{next}", Self::NAME))?;
+        old.push(next);
+        let excess = old.len().saturating_sub(max_old_files);
+        for n in &old[..excess] {
+            dir.delete_file(&format!("{}.{n}", Self::NAME))?;
+        }
+        Ok(())
+    }
+}
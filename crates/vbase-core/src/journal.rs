@@ -19,6 +19,11 @@ impl Journal {
         self.0.path()
     }
 
+    /// Limits the reader to the first `size` bytes of the file.
+    pub(crate) fn limit(self, size: u64) -> Self {
+        Self(self.0.limit(size))
+    }
+
     /// Reads a batch with its LSN from the file.
     pub(crate) fn read(&mut self) -> Result<Option<(u64, &[u8])>> {
         match self.0.read()? {
@@ -32,24 +37,57 @@ impl Journal {
 }
 
 /// A journal file writer.
-pub(crate) struct JournalWriter(FileWriter);
+pub(crate) struct JournalWriter {
+    id: u64,
+    epoch: u64,
+    file: FileWriter,
+}
 
 impl JournalWriter {
-    pub(crate) fn new(file: SequentialFileWriter) -> Self {
-        Self(FileWriter::new(file))
+    pub(crate) fn new(id: u64, epoch: u64, file: SequentialFileWriter) -> Self {
+        Self {
+            id,
+            epoch,
+            file: FileWriter::new(file),
+        }
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the epoch of the database that writes the file.
+    pub(crate) fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.file.size()
     }
 
     pub(crate) fn sync(&mut self) -> Result<()> {
-        self.0.sync().map_err(Into::into)
+        self.file.sync().map_err(Into::into)
+    }
+
+    /// Returns the number of writes queued by `Options::journal_write_queue`.
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.file.queue_depth()
+    }
+
+    /// Writes an encoded record, which starts with the LSN and the epoch, to
+    /// the file.
+    pub(crate) fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        self.file.write(record).map_err(Into::into)
     }
 
-    /// Writes a batch with its LSN to the file.
+    /// Writes a batch with its LSN and the epoch to the file.
     pub(crate) fn write<F>(&mut self, lsn: u64, append: F) -> Result<()>
     where
         F: FnOnce(&mut RecordWriter) -> Result<()>,
     {
-        let mut record = self.0.record();
+        let mut record = self.file.record();
         record.append_varint(lsn)?;
+        record.append_varint(self.epoch)?;
         append(&mut record)?;
         record.finish()?;
         Ok(())
@@ -1,15 +1,23 @@
 mod core;
 pub use core::Core;
+pub use core::EngineRef;
+pub use core::Snapshot;
 pub use core::WriteBatch;
+pub use core::WriteStallStats;
+
+mod file;
+pub use file::Identity;
 
 pub mod error;
 pub use error::Error;
 pub use error::Result;
 
+pub mod backup;
 pub mod engine;
 pub mod options;
 
-mod file;
+mod config;
+mod info_log;
 mod journal;
 mod manifest;
 mod pipeline;
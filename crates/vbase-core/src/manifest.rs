@@ -3,12 +3,69 @@ use vbase_util::codec::Decode;
 use vbase_util::codec::Encode;
 use vbase_util::crc32::checksum;
 
-#[derive(Message)]
+/// The current on-disk format version.
+///
+/// This covers the manifest and journal files. Bump it when the format
+/// changes in a way that older versions can not read. Manifests written
+/// before versioning decode as version 0, which is the same format as
+/// version 1. Version 2 adds batch versions to journal records, starting
+/// from [`Desc::versioned_lsn`]. Version 3 adds epochs to journal records,
+/// starting from [`Desc::fenced_lsn`].
+pub(crate) const FORMAT_VERSION: u32 = 3;
+
+#[derive(Clone, Message)]
 pub(crate) struct Desc {
     #[prost(tag = "1", uint64)]
     pub(crate) last_id: u64,
     #[prost(tag = "2", repeated, message)]
     pub(crate) engines: Vec<EngineDesc>,
+    /// Increases by one each time the manifest is switched.
+    #[prost(tag = "3", uint64)]
+    pub(crate) generation: u64,
+    #[prost(tag = "4", repeated, message)]
+    pub(crate) archived_engines: Vec<EngineDesc>,
+    /// The format version of the database.
+    #[prost(tag = "5", uint32)]
+    pub(crate) format_version: u32,
+    /// The first LSN of journal records with batch versions.
+    ///
+    /// Records before this LSN were written by older versions without batch
+    /// versions. This is 0 until the first one is written.
+    #[prost(tag = "6", uint64)]
+    pub(crate) versioned_lsn: u64,
+    /// Increases by one each time the database is opened.
+    ///
+    /// Journal records carry the epoch of the database that wrote them, so
+    /// that records from a database opened later can be detected if two
+    /// processes manage to open the database at the same time.
+    #[prost(tag = "7", uint64)]
+    pub(crate) epoch: u64,
+    /// The first LSN of journal records with epochs.
+    ///
+    /// This is 0 until the first one is written.
+    #[prost(tag = "8", uint64)]
+    pub(crate) fenced_lsn: u64,
+}
+
+impl Desc {
+    /// Moves engine `id` to the archived engines, which has persisted writes
+    /// up to `persisted_lsn`.
+    pub(crate) fn archive_engine(&mut self, id: u64, persisted_lsn: u64) {
+        if let Some(i) = self.engines.iter().position(|e| e.id == id) {
+            let mut engine = self.engines.remove(i);
+            engine.persisted_lsn = persisted_lsn;
+            self.archived_engines.push(engine);
+        }
+    }
+
+    /// Moves archived engine `id` back to the engines.
+    pub(crate) fn restore_engine(&mut self, id: u64) {
+        if let Some(i) = self.archived_engines.iter().position(|e| e.id == id) {
+            let mut engine = self.archived_engines.remove(i);
+            engine.persisted_lsn = 0;
+            self.engines.push(engine);
+        }
+    }
 }
 
 impl Desc {
@@ -33,10 +90,16 @@ impl Desc {
     }
 }
 
-#[derive(Message)]
+#[derive(Clone, Message)]
 pub(crate) struct EngineDesc {
     #[prost(tag = "1", uint64)]
     pub(crate) id: u64,
     #[prost(tag = "2", string)]
     pub(crate) name: String,
+    /// The last LSN persisted by an archived engine.
+    ///
+    /// Journals after this LSN are kept until the engine is restored, since
+    /// the rest of its writes are only in them.
+    #[prost(tag = "3", uint64)]
+    pub(crate) persisted_lsn: u64,
 }
@@ -1,12 +1,25 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
+use vbase_env::MockEnv;
 use vbase_env::boxed::Dir;
 use vbase_env::boxed::Env;
+use vbase_util::clock::Clock;
+use vbase_util::clock::SystemClock;
+use vbase_util::sync::Arc;
 
 use crate::Error;
 use crate::Result;
+use crate::config;
+use crate::engine::Bucket;
 use crate::engine::Engine;
-use crate::engine::internal::EngineHandle;
+use crate::engine::ext::API_VERSION;
+use crate::engine::ext::BucketHandle as _;
+use crate::engine::ext::EngineHandle;
 
 type OpenEngine = Box<dyn FnOnce(u64, Dir) -> Result<Box<dyn EngineHandle>>>;
 
@@ -18,6 +31,7 @@ pub struct Builder {
     pub engines: HashMap<String, OpenEngine>,
     pub error_if_exists: bool,
     pub error_if_not_exist: bool,
+    pub restore_engines: HashSet<String>,
 }
 
 impl Builder {
@@ -27,12 +41,32 @@ impl Builder {
     }
 
     /// Registers an engine.
+    ///
+    /// Opening fails with [`Error::InvalidArgument`] if the engine implements
+    /// a different version of the engine API.
     pub fn engine<E: Engine>(mut self) -> Self {
-        let open = |id, dir| E::open(id, dir).map(|h| Box::new(h) as _);
+        let open = |id, dir| {
+            if E::API_VERSION != API_VERSION {
+                return Err(Error::InvalidArgument(format!(
+                    "engine {} implements API version {}, but the database requires {API_VERSION}",
+                    E::NAME,
+                    E::API_VERSION,
+                )));
+            }
+            E::open(id, dir).map(|h| Box::new(h) as _)
+        };
         self.engines.insert(E::NAME.into(), Box::new(open));
         self
     }
 
+    /// Registers an engine and restores it from the archive.
+    ///
+    /// See [`Self::engine`] for more details.
+    pub fn restore_engine<E: Engine>(mut self) -> Self {
+        self.restore_engines.insert(E::NAME.into());
+        self.engine::<E>()
+    }
+
     /// Validates the builder.
     pub(crate) fn validate(&self) -> Result<()> {
         if self.error_if_exists && self.error_if_not_exist {
@@ -48,7 +82,26 @@ impl Builder {
 #[derive(Clone, Debug)]
 pub struct Options {
     pub(crate) env: Env,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) journal_env: Option<Env>,
+    pub(crate) paths: PathMap,
     pub(crate) journal_file_size: usize,
+    pub(crate) journal_write_queue: usize,
+    pub(crate) max_batch_size: usize,
+    pub(crate) background_publish: bool,
+    pub(crate) steal_stale_lock: bool,
+    pub(crate) archive_sink: Option<Arc<dyn ArchiveSink>>,
+    pub(crate) trash_retention: Option<Duration>,
+    pub(crate) trash_purge_rate: u64,
+    pub(crate) in_memory: bool,
+    pub(crate) batch_pool_size: usize,
+    pub(crate) write_slowdown_size: usize,
+    pub(crate) write_slowdown_delay: Duration,
+    pub(crate) write_stop_size: usize,
+    pub(crate) high_priority_threads: usize,
+    pub(crate) low_priority_threads: usize,
+    pub(crate) info_log_size: usize,
+    pub(crate) info_log_files: usize,
 }
 
 impl Options {
@@ -64,14 +117,363 @@ impl Options {
         Ok(Self::with_env(env))
     }
 
+    /// Creates options for a database that only lives in memory.
+    ///
+    /// The database is stored in a [`MockEnv`], and synchronizations are
+    /// skipped since there is nothing to make durable. Databases opened with
+    /// clones of the options share the same memory, so a database can be
+    /// reopened with a clone, but the data is gone once all of them are
+    /// dropped.
+    ///
+    /// [`MockEnv`]: vbase_env::MockEnv
+    pub fn in_memory() -> Self {
+        let mut options = Self::with_env(Env::new(MockEnv::default()));
+        options.in_memory = true;
+        options
+    }
+
+    /// Loads options from a TOML file in the local file system.
+    ///
+    /// Keys are the names of the setters, with a `_ms` suffix for durations
+    /// in milliseconds. Sizes can also be `"unlimited"`, and engine
+    /// directories are set in the `engine_dirs` table keyed by engine names:
+    ///
+    /// ```toml
+    /// journal_dir = "/fast/journals"
+    /// journal_file_size = 16_777_216
+    /// trash_retention_ms = 3600000 # one hour
+    ///
+    /// [engine_dirs]
+    /// Tree = "/large/tree"
+    /// ```
+    ///
+    /// Options that are not in the file keep their defaults. Options that can
+    /// not be represented in text, such as the environment, must be set with
+    /// their setters.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if the file is invalid or contains
+    /// unknown options. Floats, dates and arrays are invalid, since no option
+    /// takes them.
+    pub fn from_toml(path: impl AsRef<Path>) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        text.parse()
+    }
+
+    /// Loads options from the TOML file `name` in `dir`.
+    ///
+    /// This is the same as [`Self::from_toml`], but reads through the
+    /// environment of `dir`, so it also works with a [`MockEnv`].
+    pub fn from_toml_file(dir: &Dir, name: &str) -> Result<Self> {
+        let data = dir.read_file(name)?;
+        let text = String::from_utf8(data)
+            .map_err(|_| Error::InvalidArgument(format!("{name} is not valid UTF-8")))?;
+        text.parse()
+    }
+
+    /// Formats the options in the format of [`Self::from_toml`].
+    pub fn to_toml(&self) -> String {
+        config::format(self)
+    }
+
     /// Creates options with the given environment.
     fn with_env(env: Env) -> Self {
         Self {
             env,
+            clock: Arc::new(SystemClock),
+            journal_env: None,
+            paths: PathMap::default(),
             journal_file_size: 64 << 20,
+            journal_write_queue: 0,
+            max_batch_size: usize::MAX,
+            background_publish: false,
+            steal_stale_lock: false,
+            archive_sink: None,
+            trash_retention: None,
+            trash_purge_rate: 0,
+            in_memory: false,
+            batch_pool_size: 0,
+            write_slowdown_size: usize::MAX,
+            write_slowdown_delay: Duration::from_millis(1),
+            write_stop_size: usize::MAX,
+            high_priority_threads: 1,
+            low_priority_threads: 1,
+            info_log_size: 0,
+            info_log_files: 10,
         }
     }
 
+    /// Sets the environment of the database.
+    ///
+    /// This turns off [`Self::in_memory`].
+    ///
+    /// Default: the local file system
+    pub fn env(mut self, env: Env) -> Self {
+        self.env = env;
+        self.in_memory = false;
+        self
+    }
+
+    /// Sets the clock of the database.
+    ///
+    /// All time-dependent behaviors read the time from this clock, such as
+    /// the expiration of trash files, and rate limits and write delays sleep
+    /// with it.
+    ///
+    /// Default: the system clock
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Places journal files in `env` instead of the database environment.
+    ///
+    /// This is useful to put journals on a dedicated storage, such as
+    /// battery-backed memory. Journal files are placed in the same path as
+    /// the database, unless [`Self::journal_dir`] is set.
+    ///
+    /// Default: the database environment
+    pub fn journal_env(mut self, env: Env) -> Self {
+        self.journal_env = Some(env);
+        self
+    }
+
+    /// Places journal files in `path` instead of the database directory.
+    ///
+    /// The path is resolved in the journal environment if
+    /// [`Self::journal_env`] is set.
+    ///
+    /// This is useful to put journals on a faster storage. The same path must
+    /// be used every time the database is opened, and it must not be shared
+    /// with other databases.
+    ///
+    /// Default: the database directory
+    pub fn journal_dir(mut self, path: impl Into<String>) -> Self {
+        self.paths.journal = Some(path.into());
+        self
+    }
+
+    /// Places the directory of engine `E` in `path` instead of the database
+    /// directory.
+    ///
+    /// The same path must be used every time the database is opened, and it
+    /// must not be shared with other databases.
+    ///
+    /// Default: the database directory
+    pub fn engine_dir<E: Engine>(mut self, path: impl Into<String>) -> Self {
+        self.paths.engines.insert(E::NAME.into(), path.into());
+        self
+    }
+
+    /// Sets the size of a journal file in bytes.
+    ///
+    /// Writes switch to a new journal file once the current one reaches this
+    /// size. A record is never split across files, so a file can be larger
+    /// than this size by up to one record.
+    ///
+    /// Default: 64 MiB
+    pub fn journal_file_size(mut self, size: usize) -> Self {
+        self.journal_file_size = size;
+        self
+    }
+
+    /// Writes journal files in a background thread with a queue of `size`
+    /// records.
+    ///
+    /// Writes without [`WriteOptions::sync`] return once the record is
+    /// queued, so the latency is bounded by memory operations. Synchronous
+    /// writes wait until all queued records are written and synchronized. If
+    /// the queue is full, writes block until it drains. Queued records that
+    /// have not been written are lost on a crash, like unsynchronized data
+    /// in the system buffer. The number of queued records is reported by
+    /// the `vbase.journal-queue-depth` property.
+    ///
+    /// A zero `size` writes journal files in the writing threads.
+    ///
+    /// Default: 0
+    pub fn journal_write_queue(mut self, size: usize) -> Self {
+        self.journal_write_queue = size;
+        self
+    }
+
+    /// Sets the maximum size of a write batch in bytes.
+    ///
+    /// Writing a larger batch fails with [`Error::InvalidArgument`]. A large
+    /// batch holds the journal lock while it is written, which stalls other
+    /// writes.
+    ///
+    /// Default: unlimited
+    pub fn max_batch_size(mut self, size: usize) -> Self {
+        self.max_batch_size = size;
+        self
+    }
+
+    /// Keeps up to `size` cleared batches for reuse.
+    ///
+    /// Batches returned to the database are cleared and handed out again,
+    /// which saves allocating buffers for every batch. A zero `size` disables
+    /// the pool.
+    ///
+    /// Default: 0
+    pub fn batch_pool_size(mut self, size: usize) -> Self {
+        self.batch_pool_size = size;
+        self
+    }
+
+    /// Delays writes when engines use `size` bytes of memory or more.
+    ///
+    /// Each write sleeps for [`Self::write_slowdown_delay`] with
+    /// [`Self::clock`] before it is written, which gives engines time to
+    /// release memory instead of letting it grow without bound.
+    ///
+    /// Default: unlimited
+    pub fn write_slowdown_size(mut self, size: usize) -> Self {
+        self.write_slowdown_size = size;
+        self
+    }
+
+    /// Sets how long a write is delayed by [`Self::write_slowdown_size`].
+    ///
+    /// Default: 1 ms
+    pub fn write_slowdown_delay(mut self, delay: Duration) -> Self {
+        self.write_slowdown_delay = delay;
+        self
+    }
+
+    /// Rejects writes when engines use `size` bytes of memory or more.
+    ///
+    /// Writes fail with [`Error::Busy`] until the usage drops, so callers can
+    /// back off. This should be larger than [`Self::write_slowdown_size`].
+    ///
+    /// Writes are only rejected if every engine releases memory in the
+    /// background. Otherwise the usage can not drop until the database is
+    /// closed, and recovery replays the same writes on the next open, so
+    /// writes are only delayed. The tree engine does not flush yet, so it
+    /// never rejects writes.
+    ///
+    /// Default: unlimited
+    pub fn write_stop_size(mut self, size: usize) -> Self {
+        self.write_stop_size = size;
+        self
+    }
+
+    /// Sets the number of threads for high-priority background jobs.
+    ///
+    /// Engines run jobs that other work waits for, such as flushes, in these
+    /// threads. See [`crate::engine::scheduler`] for details.
+    ///
+    /// Default: 1
+    pub fn high_priority_threads(mut self, threads: usize) -> Self {
+        self.high_priority_threads = threads;
+        self
+    }
+
+    /// Sets the number of threads for low-priority background jobs.
+    ///
+    /// Engines run jobs that can wait, such as compactions, in these threads.
+    ///
+    /// Default: 1
+    pub fn low_priority_threads(mut self, threads: usize) -> Self {
+        self.low_priority_threads = threads;
+        self
+    }
+
+    /// Writes database events to a `LOG` file of up to `size` bytes in the
+    /// database directory.
+    ///
+    /// The log records events such as opens, journal switches and errors,
+    /// independent of the [`log`] backend of the application, so that the
+    /// history is kept with the database. A full log, or the log of the
+    /// previous open, is renamed to `LOG.{n}`, and only the last
+    /// [`Self::info_log_files`] of them are kept. A zero `size` disables the
+    /// log.
+    ///
+    /// Default: 0
+    pub fn info_log_size(mut self, size: usize) -> Self {
+        self.info_log_size = size;
+        self
+    }
+
+    /// Keeps up to `files` old info logs.
+    ///
+    /// See [`Self::info_log_size`] for details.
+    ///
+    /// Default: 10
+    pub fn info_log_files(mut self, files: usize) -> Self {
+        self.info_log_files = files;
+        self
+    }
+
+    /// Publishes committed writes in a background thread.
+    ///
+    /// Writes must be published in order to be visible to readers. By default,
+    /// a committing thread publishes pending writes of others and waits until
+    /// its own write is published, so a slow write delays all writes behind
+    /// it. If this is set, a dedicated thread publishes writes, and a write
+    /// returns once it is committed. The write may not be visible to readers
+    /// immediately after it returns.
+    ///
+    /// Default: false
+    pub fn background_publish(mut self, background: bool) -> Self {
+        self.background_publish = background;
+        self
+    }
+
+    /// Steals the database lock if the process holding it is dead.
+    ///
+    /// A crashed process may leave the database locked on file systems that
+    /// do not release locks for dead processes, such as NFS. If this is set,
+    /// the lock is broken when the process recorded in `LOCK.info` no
+    /// longer exists. This requires that the database is only opened on one
+    /// host, since processes on other hosts can not be checked.
+    ///
+    /// Default: false
+    pub fn steal_stale_lock(mut self, steal: bool) -> Self {
+        self.steal_stale_lock = steal;
+        self
+    }
+
+    /// Passes journal files to `sink` before they are deleted.
+    ///
+    /// This is useful to ship journals to a remote storage for point-in-time
+    /// recovery. See [`ArchiveSink`] for details.
+    ///
+    /// Default: none
+    pub fn archive_sink(mut self, sink: Arc<dyn ArchiveSink>) -> Self {
+        self.archive_sink = Some(sink);
+        self
+    }
+
+    /// Moves deleted journal files to trash and purges them after `retention`.
+    ///
+    /// This gives an undo window for bugs that delete files unexpectedly.
+    /// Trash files are kept in the same directory with a `.trash` suffix, and
+    /// the expired ones are purged in the background after the database is
+    /// opened. If this is not set, files are deleted immediately and existing
+    /// trash files are purged.
+    ///
+    /// Only journal files are moved to trash. Manifest files and engine files
+    /// are still deleted immediately.
+    ///
+    /// Default: none
+    pub fn trash_retention(mut self, retention: Duration) -> Self {
+        self.trash_retention = Some(retention);
+        self
+    }
+
+    /// Limits the number of trash files purged per second.
+    ///
+    /// This spreads mass deletions over time to avoid IO spikes.
+    ///
+    /// A zero `files_per_sec` means no limit.
+    ///
+    /// Default: 0
+    pub fn trash_purge_rate(mut self, files_per_sec: u64) -> Self {
+        self.trash_purge_rate = files_per_sec;
+        self
+    }
+
     /// Validates the options.
     pub(crate) fn validate(&self) -> Result<()> {
         if self.journal_file_size == 0 {
@@ -79,6 +481,21 @@ impl Options {
                 "`journal_file_size` must not be 0".into(),
             ));
         }
+        if self.max_batch_size == 0 {
+            return Err(Error::InvalidArgument(
+                "`max_batch_size` must not be 0".into(),
+            ));
+        }
+        if self.high_priority_threads == 0 || self.low_priority_threads == 0 {
+            return Err(Error::InvalidArgument(
+                "`high_priority_threads` and `low_priority_threads` must not be 0".into(),
+            ));
+        }
+        if self.write_slowdown_size > self.write_stop_size {
+            return Err(Error::InvalidArgument(
+                "`write_slowdown_size` must not exceed `write_stop_size`".into(),
+            ));
+        }
         Ok(())
     }
 }
@@ -89,6 +506,52 @@ impl Default for Options {
     }
 }
 
+impl FromStr for Options {
+    type Err = Error;
+
+    /// Parses options in the format of [`Self::from_toml`].
+    fn from_str(s: &str) -> Result<Self> {
+        let mut options = Self::new();
+        config::parse(&mut options, s).map_err(Error::InvalidArgument)?;
+        Ok(options)
+    }
+}
+
+/// A sink to archive journal files.
+///
+/// Files are passed by the directory and the name, so that the sink can read
+/// them through the environment.
+pub trait ArchiveSink: fmt::Debug + Send + Sync + 'static {
+    /// Archives a journal file that is about to be deleted.
+    ///
+    /// The file is complete and will not be written anymore. If this returns
+    /// an error, the file is kept and the operation that deletes it fails.
+    fn archive_journal(&self, dir: &Dir, name: &str) -> Result<()>;
+
+    /// Archives the synchronized part of the active journal file.
+    ///
+    /// This is called every time the active journal is synchronized by a
+    /// write with [`WriteOptions::sync`], which keeps the recovery point
+    /// close to the latest write. The first `size` bytes of the file are
+    /// durable, and the rest is still being written. An error fails the
+    /// write, like a failed synchronization.
+    ///
+    /// Default: does nothing
+    fn sync_journal(&self, dir: &Dir, name: &str, size: u64) -> Result<()> {
+        let _ = (dir, name, size);
+        Ok(())
+    }
+}
+
+/// A mapping from database components to directories.
+///
+/// Components without a mapping are placed in the database directory.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PathMap {
+    pub(crate) journal: Option<String>,
+    pub(crate) engines: HashMap<String, String>,
+}
+
 /// Options for write operations.
 #[derive(Clone, Default)]
 pub struct WriteOptions {
@@ -103,9 +566,58 @@ impl WriteOptions {
 
     /// If true, the write will be synchronized to the storage.
     ///
+    /// This has no effect on a database opened with [`Options::in_memory`].
+    ///
     /// Default: false
     pub fn sync(mut self, enable: bool) -> Self {
         self.sync = enable;
         self
     }
 }
+
+/// Options for file verification.
+#[derive(Clone, Default)]
+pub struct VerifyOptions {
+    pub(crate) rate_limit: u64,
+}
+
+impl VerifyOptions {
+    /// Creates default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the read throughput of verification in bytes per second.
+    ///
+    /// A zero value means no limit.
+    ///
+    /// Default: 0
+    pub fn rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.rate_limit = bytes_per_sec;
+        self
+    }
+}
+
+/// The scope of files to verify.
+#[derive(Clone, Debug)]
+pub enum VerifyScope {
+    /// All files in the database.
+    All,
+    /// Journal files.
+    Journals,
+    /// Manifest files, including those of engines.
+    Manifests,
+    /// Files of a bucket.
+    Bucket { engine_id: u64, bucket_id: u64 },
+}
+
+impl VerifyScope {
+    /// Returns a scope for files of `bucket`.
+    pub fn bucket<B: Bucket>(bucket: &B) -> Self {
+        let handle = bucket.handle();
+        Self::Bucket {
+            engine_id: handle.engine_id(),
+            bucket_id: handle.id(),
+        }
+    }
+}
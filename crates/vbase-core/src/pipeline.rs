@@ -2,12 +2,14 @@ use vbase_util::spmc_queue::Consumer;
 use vbase_util::spmc_queue::Producer;
 use vbase_util::spmc_queue::Undone;
 use vbase_util::spmc_queue::queue;
+use vbase_util::sync::Arc;
 use vbase_util::sync::atomic::AtomicBool;
 use vbase_util::sync::atomic::AtomicU64;
 use vbase_util::sync::atomic::Ordering::Acquire;
 use vbase_util::sync::atomic::Ordering::Relaxed;
 use vbase_util::sync::atomic::Ordering::Release;
 use vbase_util::thread;
+use vbase_util::thread::JoinHandle;
 use vbase_util::thread::Thread;
 
 /// A write in the pipeline.
@@ -86,14 +88,40 @@ impl WriteSubmitter {
 
 /// The committer side of the pipeline.
 pub(crate) struct WriteCommitter {
-    lsn: AtomicU64,
-    consumer: Consumer<Write, QUEUE_SIZE>,
+    inner: Arc<Committer>,
+    publisher: Option<Publisher>,
 }
 
 impl WriteCommitter {
     /// Commits the write and publishes its LSN.
+    ///
+    /// If there is a publisher thread, this function returns without waiting
+    /// for the write to be published.
     pub(crate) fn commit(&self, handle: WriteHandle) {
         let done = handle.0.done();
+        if let Some(publisher) = &self.publisher {
+            drop(done);
+            publisher.wake();
+            return;
+        }
+        self.inner.publish_all();
+        done.wait();
+    }
+
+    /// Returns the last published LSN.
+    pub(crate) fn last_lsn(&self) -> u64 {
+        self.inner.lsn.load(Acquire)
+    }
+}
+
+struct Committer {
+    lsn: AtomicU64,
+    consumer: Consumer<Write, QUEUE_SIZE>,
+}
+
+impl Committer {
+    /// Publishes all committed writes in order.
+    fn publish_all(&self) {
         while let Some(item) = self.consumer.dequeue() {
             // If multiple writes are committed and published at the same time,
             // the order of them is not important, because all of them are visible.
@@ -101,7 +129,6 @@ impl WriteCommitter {
             item.publish();
             item.wake();
         }
-        done.wait();
     }
 
     /// Publishes `lsn` if it is greater than the current LSN.
@@ -114,20 +141,60 @@ impl WriteCommitter {
             }
         }
     }
+}
 
-    /// Returns the last published LSN.
-    pub(crate) fn last_lsn(&self) -> u64 {
-        self.lsn.load(Acquire)
+/// A thread that publishes committed writes in the background.
+struct Publisher {
+    handle: Option<JoinHandle<()>>,
+    is_closed: Arc<AtomicBool>,
+}
+
+impl Publisher {
+    fn spawn(committer: Arc<Committer>) -> Self {
+        let is_closed = Arc::new(AtomicBool::new(false));
+        let closed = is_closed.clone();
+        let handle = thread::spawn(move || {
+            while !closed.load(Acquire) {
+                committer.publish_all();
+                thread::park();
+            }
+            committer.publish_all();
+        });
+        Self {
+            handle: Some(handle),
+            is_closed,
+        }
+    }
+
+    fn wake(&self) {
+        if let Some(handle) = &self.handle {
+            handle.thread().unpark();
+        }
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        self.is_closed.store(true, Release);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
     }
 }
 
 /// Creates a pipeline with the last LSN.
-pub(crate) fn create_pipeline(lsn: u64) -> (WriteSubmitter, WriteCommitter) {
+///
+/// If `background` is true, committed writes are published by a dedicated
+/// thread instead of the committing threads.
+pub(crate) fn create_pipeline(lsn: u64, background: bool) -> (WriteSubmitter, WriteCommitter) {
     let (producer, consumer) = queue();
     let submitter = WriteSubmitter { lsn, producer };
-    let committer = WriteCommitter {
+    let inner = Arc::new(Committer {
         lsn: AtomicU64::new(lsn),
         consumer,
-    };
+    });
+    let publisher = background.then(|| Publisher::spawn(inner.clone()));
+    let committer = WriteCommitter { inner, publisher };
     (submitter, committer)
 }
@@ -1,3 +1,62 @@
+//! The extension API to build database engines.
+//!
+//! An engine implements the traits in [`engine::ext`], which makes it
+//! usable through the public traits in [`engine`]. Engines should only
+//! depend on this crate, which re-exports everything they need. The
+//! conformance tests in `engine::testkit` (with the `test` feature) check
+//! the contract below.
+//!
+//! # Lifecycle
+//!
+//! The database opens each registered engine with [`Engine::open`], passing
+//! a unique engine id and a directory owned by the engine. The directory is
+//! kept across opens, unless the engine is archived. The engine handle is
+//! dropped when the database is closed, and it must not be used afterwards.
+//!
+//! Right after an engine is opened, it gets a [`Scheduler`] from
+//! [`EngineHandle::set_scheduler`] to run background jobs, such as flushes
+//! and compactions, in threads shared by all engines. The threads are shut
+//! down before engines are dropped, and queued jobs may never run.
+//!
+//! # Batch format
+//!
+//! A write batch for an engine is the concatenation of the bytes produced by
+//! its [`Writer`]s, one for each bucket written in the batch. The database
+//! never interprets these bytes. It stores them in journals as they are and
+//! passes them to [`EngineHandle::write`] with the LSN of the batch.
+//!
+//! Journals outlive the engine version that wrote them, so each batch is
+//! stored with the [`EngineHandle::batch_version`] of the engine. An engine
+//! that changes its batch format bumps the version, and replays batches of
+//! each version it supports in [`EngineHandle::replay`].
+//!
+//! # Recovery
+//!
+//! After an engine is opened, [`EngineHandle::last_lsn`] must return the
+//! last LSN that the engine has persisted. The database then replays every
+//! batch in the journals with a larger LSN in order, after checking the
+//! version with [`EngineHandle::supports_batch_version`]. Writes are only
+//! persisted by journals, so an engine that persists nothing returns 0 and
+//! gets all of its batches replayed.
+//!
+//! # Versioning
+//!
+//! Engines declare the version of this API that they implement with
+//! [`Engine::API_VERSION`], and the database refuses to open an engine with
+//! a different version. The version is bumped on every breaking change to
+//! the traits or the contract above.
+//!
+//! [`Engine::open`]: engine::ext::Engine::open
+//! [`Scheduler`]: engine::scheduler::Scheduler
+//! [`EngineHandle::set_scheduler`]: engine::ext::EngineHandle::set_scheduler
+//! [`Engine::API_VERSION`]: engine::ext::Engine::API_VERSION
+//! [`EngineHandle::write`]: engine::ext::EngineHandle::write
+//! [`EngineHandle::last_lsn`]: engine::ext::EngineHandle::last_lsn
+//! [`EngineHandle::batch_version`]: engine::ext::EngineHandle::batch_version
+//! [`EngineHandle::supports_batch_version`]: engine::ext::EngineHandle::supports_batch_version
+//! [`EngineHandle::replay`]: engine::ext::EngineHandle::replay
+//! [`Writer`]: engine::ext::Writer
+
 #[doc(inline)]
 pub use vbase_env as env;
 #[doc(inline)]
@@ -8,5 +67,6 @@ pub use vbase_util as util;
 mod core {
     pub use vbase_core::engine;
     pub use vbase_core::error;
+    pub use vbase_core::options;
 }
 pub use core::*;
@@ -2,6 +2,14 @@ use std::fmt;
 use std::io::Error;
 use std::io::Result;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::SequentialFileWriter as _;
 
 /// A wrapper for [`crate::Env`] objects.
 #[derive(Clone)]
@@ -124,6 +132,13 @@ impl Dir {
             .context(|| format!("delete {path}"))
     }
 
+    /// See [`crate::Dir::rename_dir`].
+    pub fn rename_dir(&self, from: &str, to: &str) -> Result<()> {
+        self.dir
+            .rename_dir(from, to)
+            .context(|| format!("rename {} to {}", self.join(from), self.join(to)))
+    }
+
     /// See [`crate::Dir::lock_file`].
     pub fn lock_file(&self, name: &str) -> Result<LockedFile> {
         self.dir
@@ -184,7 +199,29 @@ impl Dir {
         self.dir
             .create_sequential_file(name)
             .context(|| format!("create {path}"))
-            .map(|file| SequentialFileWriter { file, path })
+            .map(|file| SequentialFileWriter {
+                file,
+                path,
+                depth: None,
+            })
+    }
+
+    /// Creates a temporary file for sequential writes.
+    ///
+    /// The file is named with `prefix` and a unique suffix, so concurrent
+    /// temporary files never collide. The file is deleted on drop unless it
+    /// is persisted with [`TempFile::persist`].
+    pub fn create_temp_file(&self, prefix: &str) -> Result<TempFile<'_>> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT_ID.fetch_add(1, Relaxed);
+        let name = format!("{prefix}.{id}{}", TempFile::SUFFIX);
+        let file = self.create_sequential_file(&name)?;
+        Ok(TempFile {
+            dir: self,
+            name,
+            file,
+            is_persisted: false,
+        })
     }
 }
 
@@ -196,11 +233,14 @@ impl fmt::Debug for Dir {
 
 /// A wrapper for [`crate::LockedFile`] objects.
 pub struct LockedFile {
-    #[allow(dead_code)]
     file: Box<dyn crate::LockedFile>,
 }
 
-impl crate::LockedFile for LockedFile {}
+impl crate::LockedFile for LockedFile {
+    fn unlock(&self) -> Result<()> {
+        self.file.unlock()
+    }
+}
 
 /// A wrapper for [`crate::PositionalFile`] objects.
 pub struct PositionalFile {
@@ -252,6 +292,8 @@ impl crate::SequentialFile for SequentialFile {
 pub struct SequentialFileWriter {
     file: Box<dyn crate::SequentialFileWriter>,
     path: String,
+    /// The number of queued writes if writes are in the background.
+    depth: Option<Arc<AtomicUsize>>,
 }
 
 impl SequentialFileWriter {
@@ -259,6 +301,36 @@ impl SequentialFileWriter {
     pub fn path(&self) -> &str {
         &self.path
     }
+
+    /// Moves writes to a background thread.
+    ///
+    /// Writes return once the data is queued, and [`sync`] waits until all
+    /// queued data is written and synchronized. At most `max_pending` writes
+    /// can be queued, after which writes block until the queue drains.
+    ///
+    /// Writes are only reported through [`sync`]. If a queued write fails,
+    /// the file is poisoned: every later sync returns the error, and later
+    /// writes fail without being queued.
+    ///
+    /// [`sync`]: crate::SequentialFileWriter::sync
+    pub fn into_background(self, max_pending: usize) -> Result<Self> {
+        let path = self.path.clone();
+        let file = BackgroundFileWriter::spawn(self, max_pending)?;
+        let depth = Some(file.depth.clone());
+        Ok(Self {
+            file: Box::new(file),
+            path,
+            depth,
+        })
+    }
+
+    /// Returns the number of queued writes that are not written yet.
+    ///
+    /// This is always 0 if writes are not moved to a background thread with
+    /// [`Self::into_background`].
+    pub fn queue_depth(&self) -> usize {
+        self.depth.as_ref().map_or(0, |depth| depth.load(Relaxed))
+    }
 }
 
 impl crate::SequentialFileWriter for SequentialFileWriter {
@@ -277,6 +349,182 @@ impl crate::SequentialFileWriter for SequentialFileWriter {
     }
 }
 
+/// A file writer that writes in a background thread.
+struct BackgroundFileWriter {
+    sender: Option<mpsc::SyncSender<Op>>,
+    handle: Option<thread::JoinHandle<()>>,
+    offset: u64,
+    /// The first error of queued writes or syncs, which poisons the file.
+    error: Arc<Mutex<Option<Error>>>,
+    /// The number of queued writes, including the one being written.
+    depth: Arc<AtomicUsize>,
+}
+
+enum Op {
+    Write(Vec<u8>),
+    Sync(mpsc::Sender<Result<()>>),
+}
+
+impl BackgroundFileWriter {
+    fn spawn(mut file: SequentialFileWriter, max_pending: usize) -> Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(max_pending);
+        let offset = file.offset();
+        let error = Arc::new(Mutex::new(None));
+        let depth = Arc::new(AtomicUsize::new(0));
+        let handle = {
+            let error = error.clone();
+            let depth = depth.clone();
+            thread::Builder::new()
+                .name(format!("writer:{}", file.path()))
+                .spawn(move || {
+                    for op in receiver {
+                        match op {
+                            Op::Write(data) => {
+                                // Skip the remaining writes after an error.
+                                if error.lock().unwrap().is_none()
+                                    && let Err(e) = file.write_exact(&data)
+                                {
+                                    *error.lock().unwrap() = Some(e);
+                                }
+                                depth.fetch_sub(1, Relaxed);
+                            }
+                            Op::Sync(tx) => {
+                                let result = match Self::copy_error(&error) {
+                                    Some(e) => Err(e),
+                                    // A failed sync may have lost written data,
+                                    // so it poisons the file as well.
+                                    None => file.sync().inspect_err(|e| {
+                                        *error.lock().unwrap() = Some(copy(e));
+                                    }),
+                                };
+                                let _ = tx.send(result);
+                            }
+                        }
+                    }
+                })?
+        };
+        Ok(Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            offset,
+            error,
+            depth,
+        })
+    }
+
+    fn send(&self, op: Op) -> Result<()> {
+        let sender = self.sender.as_ref().unwrap();
+        sender
+            .send(op)
+            .map_err(|_| Error::other("background writer exited"))
+    }
+
+    /// Returns a copy of the first error, if any.
+    fn copy_error(error: &Mutex<Option<Error>>) -> Option<Error> {
+        error.lock().unwrap().as_ref().map(copy)
+    }
+}
+
+impl Drop for BackgroundFileWriter {
+    fn drop(&mut self) {
+        // Close the channel and wait for queued writes.
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl crate::SequentialFileWriter for BackgroundFileWriter {
+    fn sync(&mut self) -> Result<()> {
+        let (tx, rx) = mpsc::channel();
+        self.send(Op::Sync(tx))?;
+        rx.recv()
+            .map_err(|_| Error::other("background writer exited"))?
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        // The error belongs to an earlier write, so it is left to sync.
+        if self.error.lock().unwrap().is_some() {
+            return Err(Error::other("poisoned by an earlier failed write"));
+        }
+        // Count the write before sending, so that the thread never sees it
+        // done before it is counted.
+        self.depth.fetch_add(1, Relaxed);
+        if let Err(e) = self.send(Op::Write(buf.to_vec())) {
+            self.depth.fetch_sub(1, Relaxed);
+            return Err(e);
+        }
+        self.offset += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+fn copy(e: &Error) -> Error {
+    Error::new(e.kind(), e.to_string())
+}
+
+/// A temporary file created by [`Dir::create_temp_file`].
+pub struct TempFile<'a> {
+    dir: &'a Dir,
+    name: String,
+    file: SequentialFileWriter,
+    is_persisted: bool,
+}
+
+impl TempFile<'_> {
+    const SUFFIX: &'static str = ".tmp";
+
+    /// Returns true if `name` is a temporary file name.
+    ///
+    /// Temporary files may be left behind if the process crashes, so owners
+    /// of a directory are expected to clean them up.
+    pub fn is_temp(name: &str) -> bool {
+        name.ends_with(Self::SUFFIX)
+    }
+
+    /// Returns the name of the file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Synchronizes the file and renames it to `name`.
+    ///
+    /// This function replaces the original file if `name` already exists.
+    pub fn persist(mut self, name: &str) -> Result<()> {
+        self.file.sync()?;
+        self.dir.rename_file(&self.name, name)?;
+        self.is_persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile<'_> {
+    fn drop(&mut self) {
+        if !self.is_persisted {
+            let _ = self.dir.delete_file(&self.name);
+        }
+    }
+}
+
+impl crate::SequentialFileWriter for TempFile<'_> {
+    fn sync(&mut self) -> Result<()> {
+        self.file.sync()
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn offset(&self) -> u64 {
+        self.file.offset()
+    }
+}
+
 /// An extension to add context to [`Error`].
 trait Context<T> {
     fn context<F, C>(self, context: F) -> Result<T>
@@ -297,3 +545,139 @@ impl<T> Context<T> for Result<T> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::*;
+    use crate::MockDir;
+
+    #[test]
+    fn test_background_writer() -> Result<()> {
+        let dir = Dir::new(Box::new(MockDir::default()), "/");
+        let mut file = dir.create_sequential_file("test")?.into_background(1)?;
+        for _ in 0..10 {
+            file.write_exact(b"test")?;
+        }
+        assert_eq!(file.offset(), 40);
+        file.sync()?;
+        assert_eq!(dir.read_file("test")?, b"test".repeat(10));
+        assert_eq!(file.queue_depth(), 0);
+
+        // Queued writes are written on drop.
+        file.write_exact(b"test")?;
+        drop(file);
+        assert_eq!(dir.read_file("test")?.len(), 44);
+        Ok(())
+    }
+
+    #[test]
+    fn test_background_writer_queue_depth() -> Result<()> {
+        /// A file whose writes wait for a signal.
+        struct BlockedFile(Mutex<mpsc::Receiver<()>>);
+
+        impl crate::SequentialFileWriter for BlockedFile {
+            fn sync(&mut self) -> Result<()> {
+                Ok(())
+            }
+
+            fn write(&mut self, buf: &[u8]) -> Result<usize> {
+                self.0.lock().unwrap().recv().unwrap();
+                Ok(buf.len())
+            }
+
+            fn offset(&self) -> u64 {
+                0
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let file = SequentialFileWriter {
+            file: Box::new(BlockedFile(Mutex::new(rx))),
+            path: "test".into(),
+            depth: None,
+        };
+        assert_eq!(file.queue_depth(), 0);
+        let mut file = file.into_background(4)?;
+        for depth in 1..=3 {
+            file.write_exact(b"test")?;
+            assert_eq!(file.queue_depth(), depth);
+        }
+        for _ in 0..3 {
+            tx.send(()).unwrap();
+        }
+        file.sync()?;
+        assert_eq!(file.queue_depth(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_background_writer_error() -> Result<()> {
+        /// A file that fails writes beyond `limit` bytes.
+        struct LimitedFile {
+            size: usize,
+            limit: usize,
+        }
+
+        impl crate::SequentialFileWriter for LimitedFile {
+            fn sync(&mut self) -> Result<()> {
+                Ok(())
+            }
+
+            fn write(&mut self, buf: &[u8]) -> Result<usize> {
+                if self.size + buf.len() > self.limit {
+                    return Err(ErrorKind::StorageFull.into());
+                }
+                self.size += buf.len();
+                Ok(buf.len())
+            }
+
+            fn offset(&self) -> u64 {
+                self.size as u64
+            }
+        }
+
+        let file = SequentialFileWriter {
+            file: Box::new(LimitedFile { size: 0, limit: 4 }),
+            path: "test".into(),
+            depth: None,
+        };
+        let mut file = file.into_background(4)?;
+        file.write_exact(b"test")?;
+        file.sync()?;
+
+        // The failed write is reported by the next sync.
+        file.write_exact(b"test")?;
+        assert_eq!(file.sync().unwrap_err().kind(), ErrorKind::StorageFull);
+
+        // The file is poisoned afterwards.
+        assert_eq!(
+            file.write_exact(b"test").unwrap_err().kind(),
+            ErrorKind::Other
+        );
+        assert_eq!(file.sync().unwrap_err().kind(), ErrorKind::StorageFull);
+        Ok(())
+    }
+
+    #[test]
+    fn test_temp_file() -> Result<()> {
+        let dir = Dir::new(Box::new(MockDir::default()), "/");
+        let mut a = dir.create_temp_file("test")?;
+        let b = dir.create_temp_file("test")?;
+        assert_ne!(a.name(), b.name());
+        assert!(TempFile::is_temp(a.name()));
+        assert_eq!(dir.list()?.len(), 2);
+
+        // Dropped files are deleted.
+        drop(b);
+        assert_eq!(dir.list()?.len(), 1);
+
+        // Persisted files are kept.
+        a.write_exact(b"test")?;
+        a.persist("test")?;
+        assert_eq!(dir.list()?, vec!["test".to_string()]);
+        assert_eq!(dir.read_file("test")?, b"test");
+        Ok(())
+    }
+}
@@ -49,7 +49,10 @@ pub trait Env: Send + Sync {
 
 /// A directory in the environment.
 pub trait Dir: Send + Sync {
-    /// Returns the names of all entries.
+    /// Returns the names of all entries in ascending order.
+    ///
+    /// The order is the same in all environments, so callers can depend on
+    /// it without sorting the names again.
     fn list(&self) -> Result<Vec<String>>;
 
     /// Opens a directory.
@@ -73,9 +76,18 @@ pub trait Dir: Send + Sync {
     /// Returns [`ErrorKind::NotFound`] if `name` does not exist.
     fn delete_dir(&self, name: &str) -> Result<()>;
 
+    /// Renames a directory.
+    ///
+    /// # Errors
+    ///
+    /// - Returns [`ErrorKind::NotFound`] if `from` does not exist.
+    /// - Returns [`ErrorKind::AlreadyExists`] if `to` already exists.
+    fn rename_dir(&self, from: &str, to: &str) -> Result<()>;
+
     /// Locks a file.
     ///
-    /// This function creates a new file if `name` does not exist.
+    /// This function creates a new file if `name` does not exist. The content
+    /// of the file is left untouched.
     ///
     /// # Errors
     ///
@@ -133,7 +145,12 @@ pub trait Dir: Send + Sync {
 /// A locked file.
 ///
 /// Dropping the locked file unlocks it.
-pub trait LockedFile: Send + Sync {}
+pub trait LockedFile: Send + Sync {
+    /// Unlocks the file.
+    ///
+    /// Unlocking a file that has been unlocked does nothing.
+    fn unlock(&self) -> Result<()>;
+}
 
 /// A file opened for positional reads.
 pub trait PositionalFile: Send + Sync {
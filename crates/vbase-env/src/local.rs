@@ -4,6 +4,7 @@ use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Result;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 
 use crate::Dir;
@@ -61,14 +62,17 @@ impl LocalDir {
 impl Dir for LocalDir {
     fn list(&self) -> Result<Vec<String>> {
         let dir = fs::read_dir(&self.path)?;
-        dir.map(|res| {
-            res.and_then(|ent| {
-                ent.file_name()
-                    .into_string()
-                    .map_err(|name| Error::new(ErrorKind::InvalidFilename, format!("{name:?}")))
+        let mut list = dir
+            .map(|res| {
+                res.and_then(|ent| {
+                    ent.file_name()
+                        .into_string()
+                        .map_err(|name| Error::new(ErrorKind::InvalidFilename, format!("{name:?}")))
+                })
             })
-        })
-        .collect()
+            .collect::<Result<Vec<_>>>()?;
+        list.sort_unstable();
+        Ok(list)
     }
 
     fn open_dir(&self, name: &str) -> Result<Box<dyn Dir>> {
@@ -85,21 +89,38 @@ impl Dir for LocalDir {
         fs::remove_dir_all(self.path.join(name))
     }
 
+    fn rename_dir(&self, from: &str, to: &str) -> Result<()> {
+        let to = self.path.join(to);
+        if fs::exists(&to)? {
+            return Err(ErrorKind::AlreadyExists.into());
+        }
+        rename(&self.path.join(from), &to)
+    }
+
     fn lock_file(&self, name: &str) -> Result<Box<dyn LockedFile>> {
         let path = self.path.join(name);
-        let file = fs::File::create(path)?;
+        let file = open_options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
         file.try_lock()?;
         Ok(Box::new(LocalFile(file)))
     }
 
     fn read_file(&self, name: &str) -> Result<Vec<u8>> {
-        fs::read(self.path.join(name))
+        let path = self.path.join(name);
+        let mut file = open_options().read(true).open(path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Ok(data)
     }
 
     fn write_file(&self, name: &str, data: &[u8]) -> Result<()> {
         let path = self.path.join(name);
-        let mut file = fs::File::create(path)?;
+        let mut file = create_options().open(path)?;
         file.write_all(data)?;
+        // Sync metadata as well, since the file may have been created.
         file.sync_all()?;
         Ok(())
     }
@@ -109,31 +130,89 @@ impl Dir for LocalDir {
     }
 
     fn rename_file(&self, from: &str, to: &str) -> Result<()> {
-        fs::rename(self.path.join(from), self.path.join(to))
+        rename(&self.path.join(from), &self.path.join(to))
     }
 
     fn open_positional_file(&self, name: &str) -> Result<Box<dyn PositionalFile>> {
         let path = self.path.join(name);
-        let file = fs::File::open(path)?;
+        let file = open_options().read(true).open(path)?;
         Ok(Box::new(LocalFile(file)))
     }
 
     fn open_sequential_file(&self, name: &str) -> Result<Box<dyn SequentialFile>> {
         let path = self.path.join(name);
-        let file = fs::File::open(path)?;
+        let file = open_options().read(true).open(path)?;
         Ok(Box::new(LocalSequentialFile::new(file)))
     }
 
     fn create_sequential_file(&self, name: &str) -> Result<Box<dyn SequentialFileWriter>> {
         let path = self.path.join(name);
-        let file = fs::File::create(path)?;
+        let file = create_options().open(path)?;
         Ok(Box::new(LocalSequentialFile::new(file)))
     }
 }
 
+/// Returns options to open files.
+///
+/// On Windows, files are opened with all share modes, so that open files can
+/// be read, written, renamed and deleted by others, as on Unix.
+fn open_options() -> fs::OpenOptions {
+    #[allow(unused_mut)]
+    let mut options = fs::OpenOptions::new();
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        const FILE_SHARE_WRITE: u32 = 0x2;
+        const FILE_SHARE_DELETE: u32 = 0x4;
+        options.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE);
+    }
+    options
+}
+
+/// Returns options to create or truncate files for writes.
+fn create_options() -> fs::OpenOptions {
+    let mut options = open_options();
+    options.create(true).truncate(true).write(true);
+    options
+}
+
+#[cfg(not(windows))]
+fn rename(from: &Path, to: &Path) -> Result<()> {
+    fs::rename(from, to)
+}
+
+/// Renames a file, replacing `to` if it exists.
+///
+/// On Windows, replacing a file fails with [`ErrorKind::PermissionDenied`] if
+/// it is opened by others without `FILE_SHARE_DELETE` (e.g. antivirus or
+/// indexing services). Such handles are usually transient, so retry a few
+/// times before giving up.
+#[cfg(windows)]
+fn rename(from: &Path, to: &Path) -> Result<()> {
+    use std::thread;
+    use std::time::Duration;
+
+    const RETRIES: u32 = 10;
+    let mut retries = 0;
+    loop {
+        match fs::rename(from, to) {
+            Err(e) if e.kind() == ErrorKind::PermissionDenied && retries < RETRIES => {
+                retries += 1;
+                thread::sleep(Duration::from_millis(10 << retries.min(4)));
+            }
+            x => return x,
+        }
+    }
+}
+
 struct LocalFile(fs::File);
 
-impl LockedFile for LocalFile {}
+impl LockedFile for LocalFile {
+    fn unlock(&self) -> Result<()> {
+        self.0.unlock()
+    }
+}
 
 impl PositionalFile for LocalFile {
     #[cfg(unix)]
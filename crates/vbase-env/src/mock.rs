@@ -58,8 +58,15 @@ impl Dir for MockDir {
         self.0.delete_dir(name)
     }
 
+    fn rename_dir(&self, from: &str, to: &str) -> Result<()> {
+        self.0.rename_dir(from, to)
+    }
+
     fn lock_file(&self, name: &str) -> Result<Box<dyn LockedFile>> {
-        let file = self.0.create_file(name)?;
+        let file = match self.0.open_file(name) {
+            Err(e) if e.kind() == ErrorKind::NotFound => self.0.create_file(name)?,
+            x => x?,
+        };
         let lock = MockLockedFile::new(file)?;
         Ok(Box::new(lock))
     }
@@ -99,22 +106,29 @@ impl Dir for MockDir {
     }
 }
 
-struct MockLockedFile(FileHandle);
+struct MockLockedFile(Mutex<Option<FileHandle>>);
 
 impl MockLockedFile {
     fn new(file: FileHandle) -> Result<Self> {
         file.lock()?;
-        Ok(Self(file))
+        Ok(Self(Mutex::new(Some(file))))
     }
 }
 
 impl Drop for MockLockedFile {
     fn drop(&mut self) {
-        self.0.unlock();
+        let _ = self.unlock();
     }
 }
 
-impl LockedFile for MockLockedFile {}
+impl LockedFile for MockLockedFile {
+    fn unlock(&self) -> Result<()> {
+        if let Some(file) = self.0.lock().unwrap().take() {
+            file.unlock();
+        }
+        Ok(())
+    }
+}
 
 struct MockPositionalFile(FileHandle);
 
@@ -178,7 +192,9 @@ struct DirHandle(Arc<Mutex<HashMap<String, Handle>>>);
 impl DirHandle {
     fn list(&self) -> Vec<String> {
         let inner = self.0.lock().unwrap();
-        inner.keys().cloned().collect()
+        let mut list = inner.keys().cloned().collect::<Vec<_>>();
+        list.sort_unstable();
+        list
     }
 
     fn open_dir(&self, name: &str) -> Result<DirHandle> {
@@ -215,6 +231,22 @@ impl DirHandle {
         }
     }
 
+    fn rename_dir(&self, from: &str, to: &str) -> Result<()> {
+        let mut inner = self.0.lock().unwrap();
+        if inner.contains_key(to) {
+            return Err(ErrorKind::AlreadyExists.into());
+        }
+        match inner.get(from).cloned() {
+            Some(Handle::Dir(dir)) => {
+                inner.remove(from);
+                inner.insert(to.into(), Handle::Dir(dir));
+                Ok(())
+            }
+            Some(_) => Err(ErrorKind::NotADirectory.into()),
+            None => Err(ErrorKind::NotFound.into()),
+        }
+    }
+
     fn open_file(&self, name: &str) -> Result<FileHandle> {
         let inner = self.0.lock().unwrap();
         match inner.get(name).cloned() {
@@ -129,6 +129,10 @@ impl Dir for TestDir {
         self.dir().delete_dir(name)
     }
 
+    fn rename_dir(&self, from: &str, to: &str) -> Result<()> {
+        self.dir().rename_dir(from, to)
+    }
+
     fn lock_file(&self, name: &str) -> Result<Box<dyn LockedFile>> {
         self.dir().lock_file(name)
     }
@@ -178,7 +182,61 @@ mod tests {
             ErrorKind::WouldBlock
         );
         drop(file);
+        let file = dir.lock_file(name)?;
+        file.unlock()?;
         dir.lock_file(name)?;
+        // Unlocking again does nothing.
+        file.unlock()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_list() -> Result<()> {
+        let dir = TestDir::new()?;
+        for name in ["c", "a", "d", "b"] {
+            dir.write_file(name, name.as_bytes())?;
+        }
+        dir.create_dir("e")?;
+        assert_eq!(dir.list()?, ["a", "b", "c", "d", "e"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_dir() -> Result<()> {
+        let dir = TestDir::new()?;
+        dir.create_dir("a")?.write_file("file", b"a")?;
+        dir.create_dir("b")?;
+        assert_eq!(
+            dir.rename_dir("a", "b").unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+        dir.rename_dir("a", "c")?;
+        assert_eq!(dir.open_dir("c")?.read_file("file")?, b"a");
+        assert_eq!(
+            dir.rename_dir("a", "d").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_file() -> Result<()> {
+        let dir = TestDir::new()?;
+        dir.write_file("a", b"a")?;
+        dir.write_file("b", b"b")?;
+
+        // Replace and delete files while they are open.
+        let file = dir.open_positional_file("a")?;
+        let _writer = dir.create_sequential_file("c")?;
+        dir.rename_file("b", "a")?;
+        dir.rename_file("c", "b")?;
+        dir.delete_file("b")?;
+        assert_eq!(dir.read_file("a")?, b"b");
+
+        // Open files are still readable.
+        let mut buf = [0; 1];
+        assert_eq!(file.read(&mut buf, 0)?, 1);
+        assert_eq!(&buf, b"a");
         Ok(())
     }
 }
@@ -9,6 +9,8 @@ pub enum Error {
     Io(#[from] io::Error),
     #[error("{name} is corrupted: {message}")]
     Corrupted { name: String, message: String },
+    #[error("{0}")]
+    InvalidArgument(String),
 }
 
 /// A specialized [`std::result::Result`] for file operations.
@@ -13,6 +13,10 @@
 //! Fragment format:
 //!
 //! | Checksum (4B) | Size (2B) | Kind (1B) | Data |
+//!
+//! A fragment never crosses a block boundary, so its data is at most 32KB
+//! minus the header size. A record can span any number of fragments, but it
+//! must not exceed [`MAX_RECORD_SIZE`] bytes.
 
 use std::ops::Range;
 
@@ -28,12 +32,22 @@ use vbase_util::codec::Encoder;
 use vbase_util::codec::Varint;
 use vbase_util::crc32::checksum_combined;
 
+use crate::Error;
 use crate::Result;
 use crate::error::Corrupted;
 
 const BLOCK_SIZE: usize = 32 * 1024;
 const BUFFER_SIZE: usize = 32 * BLOCK_SIZE;
 const HEADER_SIZE: usize = 7;
+const MAX_FRAGMENT_SIZE: usize = BLOCK_SIZE - HEADER_SIZE;
+const _: () = assert!(MAX_FRAGMENT_SIZE <= u16::MAX as usize);
+
+/// The maximum size of a record.
+///
+/// Writing a larger record returns [`Error::InvalidArgument`].
+///
+/// [`Error::InvalidArgument`]: crate::Error::InvalidArgument
+pub const MAX_RECORD_SIZE: usize = u32::MAX as usize;
 
 /// A sequential journal file reader.
 pub struct File {
@@ -46,6 +60,9 @@ pub struct File {
     length: usize,
     /// A buffer for assembling a record.
     record: Vec<u8>,
+    /// The maximum number of bytes to read from the file.
+    limit: u64,
+    checksum: ChecksumMode,
 }
 
 impl File {
@@ -56,9 +73,26 @@ impl File {
             offset: 0,
             length: 0,
             record: Vec::new(),
+            limit: u64::MAX,
+            checksum: ChecksumMode::Verify,
         }
     }
 
+    /// Sets how fragment checksums are verified, for tests.
+    #[cfg(any(test, feature = "test"))]
+    pub fn checksum_mode(mut self, mode: ChecksumMode) -> Self {
+        self.checksum = mode;
+        self
+    }
+
+    /// Limits the reader to the first `size` bytes of the file.
+    ///
+    /// This is useful to read a file that is being written concurrently.
+    pub fn limit(mut self, size: u64) -> Self {
+        self.limit = size;
+        self
+    }
+
     /// Returns the path of the file.
     pub fn path(&self) -> &str {
         self.file.path()
@@ -95,7 +129,9 @@ impl File {
             self.offset += remain;
         }
         if self.offset >= self.length {
-            let n = self.file.read_until_end(&mut self.buffer)?;
+            let remain = self.limit.saturating_sub(self.file.offset());
+            let len = remain.min(BUFFER_SIZE as u64) as usize;
+            let n = self.file.read_until_end(&mut self.buffer[..len])?;
             if n == 0 {
                 return Ok(None);
             }
@@ -110,6 +146,12 @@ impl File {
         let crc = dec.decode::<u32>();
         let size = dec.decode::<u16>() as usize;
         let kind = dec.decode::<FragmentKind>();
+        let remain = BLOCK_SIZE - (self.offset % BLOCK_SIZE) - HEADER_SIZE;
+        if size > remain {
+            return self.path().corrupted(format!(
+                "fragment size {size} exceeds the remaining space {remain} in the block"
+            ));
+        }
         if dec.len() < size {
             return self.path().corrupted(format!(
                 "fragment size mismatch (expected {}, got {})",
@@ -119,11 +161,16 @@ impl File {
         }
         let data = dec.remove(size);
         let checksum = kind.checksum_with(data);
-        if checksum != crc {
+        if checksum != crc && self.checksum != ChecksumMode::Skip {
             return self.path().corrupted(format!(
                 "fragment checksum mismatch (expected {crc:#x}, got {checksum:#x})"
             ));
         }
+        if self.record.len() + size > MAX_RECORD_SIZE {
+            return self
+                .path()
+                .corrupted(format!("record size exceeds {MAX_RECORD_SIZE}"));
+        }
 
         self.record.extend_from_slice(data);
         self.offset += HEADER_SIZE + size;
@@ -142,6 +189,7 @@ pub struct FileWriter {
     fragment: Range<usize>,
     /// Whether the current fragment is the first fragment of a record.
     is_first_fragment: bool,
+    checksum: ChecksumMode,
 }
 
 impl FileWriter {
@@ -152,9 +200,17 @@ impl FileWriter {
             offset: 0,
             fragment: 0..0,
             is_first_fragment: true,
+            checksum: ChecksumMode::Verify,
         }
     }
 
+    /// Sets how fragment checksums are written, for tests.
+    #[cfg(any(test, feature = "test"))]
+    pub fn checksum_mode(mut self, mode: ChecksumMode) -> Self {
+        self.checksum = mode;
+        self
+    }
+
     /// Returns the path of the file.
     pub fn path(&self) -> &str {
         self.file.path()
@@ -170,16 +226,33 @@ impl FileWriter {
         self.file.sync().map_err(Into::into)
     }
 
+    /// Returns the number of writes queued in the background.
+    ///
+    /// See [`SequentialFileWriter::queue_depth`].
+    pub fn queue_depth(&self) -> usize {
+        self.file.queue_depth()
+    }
+
     /// Writes a record to the file.
+    ///
+    /// A record that fits in the current block is written as a single
+    /// fragment without going through [`RecordWriter`].
     pub fn write<T: AsRef<[u8]>>(&mut self, record: T) -> Result<()> {
+        let record = record.as_ref();
+        if self.write_full(record)? {
+            return Ok(());
+        }
         let mut w = self.record();
-        w.append(record.as_ref())?;
+        w.append(record)?;
         w.finish()
     }
 
     /// Returns a record writer for multi-part records.
     pub fn record(&mut self) -> RecordWriter<'_> {
-        RecordWriter { file: self }
+        RecordWriter {
+            file: self,
+            size: 0,
+        }
     }
 }
 
@@ -199,6 +272,23 @@ impl FileWriter {
         Ok(())
     }
 
+    /// Writes `record` as a full fragment if it fits in the current block.
+    ///
+    /// Returns false without writing anything if it does not fit.
+    fn write_full(&mut self, record: &[u8]) -> Result<bool> {
+        let start = self.fragment.start;
+        let end = start + HEADER_SIZE + record.len();
+        let block_end = (start / BLOCK_SIZE + 1) * BLOCK_SIZE;
+        if end > block_end || end > self.buffer.len() {
+            return Ok(false);
+        }
+        self.buffer[start + HEADER_SIZE..end].copy_from_slice(record);
+        self.fragment.end = end;
+        self.build_fragment(true);
+        self.flush()?;
+        Ok(true)
+    }
+
     /// Appends data to the current record.
     fn append(&mut self, mut data: &[u8]) -> Result<()> {
         loop {
@@ -206,8 +296,10 @@ impl FileWriter {
                 self.start_fragment()?;
             }
             let end = self.fragment.end;
-            let len = BLOCK_SIZE - (end % BLOCK_SIZE);
-            let len = data.len().min(len);
+            // The fragment may have filled its block in a previous append, in
+            // which case there is no space left and we move to the next block.
+            let block_end = (self.fragment.start / BLOCK_SIZE + 1) * BLOCK_SIZE;
+            let len = data.len().min(block_end - end);
             let buf = data.split_off(..len).unwrap();
             self.buffer[end..end + buf.len()].copy_from_slice(buf);
             self.fragment.end += buf.len();
@@ -244,7 +336,17 @@ impl FileWriter {
             (false, false) => FragmentKind::Middle,
         };
         let (mut enc, data) = self.buffer[self.fragment.clone()].split_at_mut(HEADER_SIZE);
-        enc.encode(kind.checksum_with(data));
+        assert!(
+            data.len() <= MAX_FRAGMENT_SIZE,
+            "fragment size {} exceeds {MAX_FRAGMENT_SIZE}",
+            data.len()
+        );
+        let checksum = match self.checksum {
+            ChecksumMode::Verify => kind.checksum_with(data),
+            ChecksumMode::Skip => 0,
+            ChecksumMode::Corrupt => !kind.checksum_with(data),
+        };
+        enc.encode(checksum);
         enc.encode(data.len() as u16);
         enc.encode(kind);
         self.fragment.start = self.fragment.end;
@@ -255,12 +357,27 @@ impl FileWriter {
 /// A journal record writer for multi-part records.
 pub struct RecordWriter<'a> {
     file: &'a mut FileWriter,
+    /// The size of the record so far.
+    size: usize,
 }
 
 impl<'a> RecordWriter<'a> {
     /// Appends a slice to the record.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the record would exceed
+    /// [`MAX_RECORD_SIZE`]. The record is incomplete after an error, so the
+    /// file should not be written anymore.
+    ///
+    /// [`Error::InvalidArgument`]: crate::Error::InvalidArgument
     pub fn append(&mut self, data: &[u8]) -> Result<()> {
-        self.file.append(data)
+        if data.len() > MAX_RECORD_SIZE - self.size {
+            return Err(Error::InvalidArgument(format!(
+                "record size exceeds {MAX_RECORD_SIZE}"
+            )));
+        }
+        self.file.append(data)?;
+        self.size += data.len();
+        Ok(())
     }
 
     /// Appends a varint to the record.
@@ -268,7 +385,7 @@ impl<'a> RecordWriter<'a> {
         let mut buf = [0; 16];
         let mut enc = BytesEncoder::new(&mut buf);
         enc.encode_varint(value);
-        self.file.append(enc.encoded_bytes())
+        self.append(enc.encoded_bytes())
     }
 
     /// Appends a varint-prefixed slice to the record.
@@ -279,11 +396,33 @@ impl<'a> RecordWriter<'a> {
 
     /// Finishes the record.
     pub fn finish(self) -> Result<()> {
+        if self.file.fragment.is_empty() {
+            // Nothing has been appended, start a fragment for an empty record.
+            self.file.start_fragment()?;
+        }
         self.file.build_fragment(true);
         self.file.flush()
     }
 }
 
+/// How fragment checksums are handled.
+///
+/// Only [`ChecksumMode::Verify`] is used outside of tests. The other modes
+/// isolate the cost of checksums in benchmarks and inject corruptions in
+/// negative tests.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ChecksumMode {
+    /// Writes and verifies checksums.
+    #[default]
+    Verify,
+    /// Writes zero checksums and skips verification.
+    Skip,
+    /// Writes checksums that never match the data.
+    ///
+    /// This is the same as [`ChecksumMode::Verify`] for readers.
+    Corrupt,
+}
+
 /// DO NOT change the values in this enum.
 #[repr(u8)]
 #[derive(Copy, Clone, Debug)]
@@ -373,4 +512,172 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_full_fragment() -> Result<()> {
+        let dir = Dir::test()?;
+        let records = [
+            vec![1; 10],
+            vec![2; MAX_FRAGMENT_SIZE - 10 - HEADER_SIZE],
+            vec![],
+            vec![3; BLOCK_SIZE - HEADER_SIZE - 1],
+            vec![4; 10],
+        ];
+        // Records written in one call are the same as multi-part records.
+        {
+            let mut file = dir.create_sequential_file("full").map(FileWriter::new)?;
+            for record in &records {
+                file.write(record)?;
+            }
+            let mut file = dir.create_sequential_file("parts").map(FileWriter::new)?;
+            for record in &records {
+                let mut w = file.record();
+                for part in record.chunks(3) {
+                    w.append(part)?;
+                }
+                w.finish()?;
+            }
+        }
+        assert_eq!(dir.read_file("full")?, dir.read_file("parts")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_mode() -> Result<()> {
+        let dir = Dir::test()?;
+        let open = |name, mode| {
+            dir.open_sequential_file(name)
+                .map(|f| File::new(f).checksum_mode(mode))
+        };
+        for (name, mode) in [
+            ("skip", ChecksumMode::Skip),
+            ("corrupt", ChecksumMode::Corrupt),
+        ] {
+            let mut file = dir
+                .create_sequential_file(name)
+                .map(|f| FileWriter::new(f).checksum_mode(mode))?;
+            file.write(b"foo")?;
+        }
+
+        let mut file = open("skip", ChecksumMode::Skip)?;
+        assert_eq!(file.read()?, Some(b"foo".as_slice()));
+        for (name, mode) in [
+            ("skip", ChecksumMode::Verify),
+            ("corrupt", ChecksumMode::Verify),
+            ("corrupt", ChecksumMode::Corrupt),
+        ] {
+            match open(name, mode)?.read() {
+                Err(Error::Corrupted { .. }) => {}
+                x => panic!("unexpected result for {name}: {x:?}"),
+            }
+        }
+        let mut file = open("corrupt", ChecksumMode::Skip)?;
+        assert_eq!(file.read()?, Some(b"foo".as_slice()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit() -> Result<()> {
+        let dir = Dir::test()?;
+        let name = "test";
+        let mut file = dir.create_sequential_file(name).map(FileWriter::new)?;
+        file.write(b"foo")?;
+        let size = file.size();
+        file.write(b"bar")?;
+        {
+            let mut file = dir.open_sequential_file(name).map(File::new)?;
+            assert_eq!(file.read()?, Some(b"foo".as_slice()));
+            assert_eq!(file.read()?, Some(b"bar".as_slice()));
+            assert_eq!(file.read()?, None);
+        }
+        {
+            let mut file = dir
+                .open_sequential_file(name)
+                .map(|f| File::new(f).limit(size))?;
+            assert_eq!(file.read()?, Some(b"foo".as_slice()));
+            assert_eq!(file.read()?, None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fragment_size() -> Result<()> {
+        let dir = Dir::test()?;
+        let name = "test";
+        {
+            let mut file = dir.create_sequential_file(name).map(FileWriter::new)?;
+            // A fragment that exactly fills a block
+            file.write(vec![1; MAX_FRAGMENT_SIZE])?;
+            assert_eq!(file.size(), BLOCK_SIZE as u64);
+            // Two fragments that exactly fill two blocks
+            file.write(vec![2; MAX_FRAGMENT_SIZE * 2])?;
+            assert_eq!(file.size(), BLOCK_SIZE as u64 * 3);
+            // A multi-part record that fills a block before the next part
+            let mut record = file.record();
+            record.append(&[3; MAX_FRAGMENT_SIZE])?;
+            record.append(&[4; 10])?;
+            record.finish()?;
+            assert_eq!(file.size(), BLOCK_SIZE as u64 * 4 + HEADER_SIZE as u64 + 10);
+            // Zero-length records
+            file.write([])?;
+            file.record().finish()?;
+            assert_eq!(
+                file.size(),
+                BLOCK_SIZE as u64 * 4 + HEADER_SIZE as u64 * 3 + 10
+            );
+        }
+        {
+            let mut file = dir.open_sequential_file(name).map(File::new)?;
+            assert_eq!(file.read()?, Some(vec![1; MAX_FRAGMENT_SIZE].as_slice()));
+            assert_eq!(
+                file.read()?,
+                Some(vec![2; MAX_FRAGMENT_SIZE * 2].as_slice())
+            );
+            let mut record = vec![3; MAX_FRAGMENT_SIZE];
+            record.extend_from_slice(&[4; 10]);
+            assert_eq!(file.read()?, Some(record.as_slice()));
+            assert_eq!(file.read()?, Some([].as_slice()));
+            assert_eq!(file.read()?, Some([].as_slice()));
+            assert_eq!(file.read()?, None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_fragment_size() -> Result<()> {
+        let dir = Dir::test()?;
+        let name = "test";
+        {
+            // A fragment that claims to cross the block boundary
+            let mut data = vec![0; BLOCK_SIZE * 2];
+            let mut enc = data.as_mut_slice();
+            enc.encode(0u32);
+            enc.encode((MAX_FRAGMENT_SIZE + 1) as u16);
+            enc.encode(FragmentKind::Full);
+            dir.write_file(name, &data)?;
+        }
+        let mut file = dir.open_sequential_file(name).map(File::new)?;
+        match file.read() {
+            Err(Error::Corrupted { .. }) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_record_size() -> Result<()> {
+        let dir = Dir::test()?;
+        let name = "test";
+        let mut file = dir.create_sequential_file(name).map(FileWriter::new)?;
+        let mut record = RecordWriter {
+            file: &mut file,
+            size: MAX_RECORD_SIZE - 3,
+        };
+        record.append(b"foo")?;
+        match record.append(b"bar") {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
 }
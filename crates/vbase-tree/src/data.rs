@@ -64,11 +64,13 @@ impl<'a> Decode<'a> for Vid<'a> {
 }
 
 /// An owned version id.
+#[allow(dead_code, reason = "not used by iterators yet")]
 pub(crate) struct OwnedVid {
     pub(crate) id: Vec<u8>,
     pub(crate) lsn: u64,
 }
 
+#[allow(dead_code, reason = "not used by iterators yet")]
 impl OwnedVid {
     pub(crate) fn new(id: Vec<u8>, lsn: u64) -> Self {
         Self { id, lsn }
@@ -198,6 +200,11 @@ impl<'a> WriteBatchIter<'a> {
     pub(crate) fn new(buf: &'a [u8]) -> Self {
         Self { buf }
     }
+
+    /// Returns the remaining data after the records.
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
 }
 
 impl<'a> Iterator for WriteBatchIter<'a> {
@@ -255,7 +262,7 @@ impl<'a> WriteRecord<'a> {
         }
     }
 
-    fn into_version(self, lsn: u64) -> (Vid<'a>, Value<'a>) {
+    pub(crate) fn into_version(self, lsn: u64) -> (Vid<'a>, Value<'a>) {
         match self {
             Self::Value(id, value) => (Vid::new(id, lsn), Value::Value(value)),
             Self::Tombstone(id) => (Vid::new(id, lsn), Value::Tombstone),
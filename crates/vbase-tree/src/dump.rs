@@ -0,0 +1,167 @@
+//! Logical dumps of buckets.
+//!
+//! A dump is a sequence of entries in id order, followed by an end marker.
+//! Each entry starts with a marker, followed by an id and a value, both
+//! prefixed with their sizes in varints.
+
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+
+use vbase_engine::util::codec::Decoder;
+use vbase_engine::util::codec::Encoder;
+use vbase_engine::util::codec::Varint;
+
+use crate::Result;
+use crate::engine::MAX_ID_SIZE;
+use crate::engine::MAX_VALUE_SIZE;
+use crate::error::Corrupted;
+
+const NAME: &str = "dump";
+
+/// The marker of an entry.
+const ENTRY: u8 = 1;
+/// The end marker.
+const END: u8 = 0;
+
+/// Writes the entries in `iter` to `w`.
+pub(crate) fn dump<I, W>(iter: I, mut w: W) -> Result<()>
+where
+    I: Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>,
+    W: Write,
+{
+    let mut buf = Vec::new();
+    for entry in iter {
+        let (id, value) = entry?;
+        buf.clear();
+        buf.put(ENTRY);
+        buf.encode(id.as_slice());
+        buf.encode(value.as_slice());
+        w.write_all(&buf)?;
+    }
+    w.write_all(&[END])?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Reads entries from `r` and passes them to `f`, until the entries take
+/// `max_size` bytes or more.
+///
+/// Returns true if the end marker is read.
+pub(crate) fn load<R, F>(mut r: R, max_size: usize, mut f: F) -> Result<bool>
+where
+    R: Read,
+    F: FnMut(&[u8], &[u8]) -> Result<()>,
+{
+    let mut id = Vec::new();
+    let mut value = Vec::new();
+    let mut size = 0;
+    while size < max_size {
+        match read_byte(&mut r)? {
+            ENTRY => {
+                read_data(&mut r, &mut id, MAX_ID_SIZE)?;
+                read_data(&mut r, &mut value, MAX_VALUE_SIZE)?;
+                f(&id, &value)?;
+                size += id.len() + value.len();
+            }
+            END => return Ok(true),
+            x => return NAME.corrupted(format!("invalid marker {x}")),
+        }
+    }
+    Ok(false)
+}
+
+/// Reads size-prefixed data into `buf`.
+fn read_data<R: Read>(r: &mut R, buf: &mut Vec<u8>, max_size: usize) -> Result<()> {
+    let size = read_varint(r)?;
+    if size > max_size as u64 {
+        return NAME.corrupted(format!(
+            "data size {size} exceeds the maximum size {max_size}"
+        ));
+    }
+    buf.resize(size as usize, 0);
+    read_exact(r, buf)
+}
+
+/// Reads the bytes of a varint and decodes them with the codec.
+fn read_varint<R: Read>(r: &mut R) -> Result<u64> {
+    let mut buf = [0; u64::MAX_VARINT_SIZE];
+    for i in 0..buf.len() {
+        buf[i] = read_byte(r)?;
+        if buf[i] < 0x80 {
+            return Ok((&buf[..=i]).decode_varint());
+        }
+    }
+    NAME.corrupted("varint overflow")
+}
+
+fn read_byte<R: Read>(r: &mut R) -> Result<u8> {
+    let mut buf = [0];
+    read_exact(r, &mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_exact<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<()> {
+    match r.read_exact(buf) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+            NAME.corrupted("unexpected end of stream")
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn load_all(data: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        let done = load(data, usize::MAX, |id, value| {
+            entries.push((id.to_vec(), value.to_vec()));
+            Ok(())
+        })?;
+        assert!(done);
+        Ok(entries)
+    }
+
+    #[test]
+    fn test() -> Result<()> {
+        let entries = vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), vec![]),
+            (vec![2; 300], vec![3; 1000]),
+        ];
+        let mut data = Vec::new();
+        dump(entries.iter().cloned().map(Ok), &mut data)?;
+        assert_eq!(load_all(&data)?, entries);
+        assert_eq!(load_all(&[END])?, []);
+
+        // Loading stops after the entries reach the size.
+        let mut r = data.as_slice();
+        let mut sizes = Vec::new();
+        while !load(&mut r, 2, |id, value| {
+            sizes.push(id.len() + value.len());
+            Ok(())
+        })? {}
+        assert_eq!(sizes, [2, 1, 1300]);
+
+        // A varint longer than the maximum size is corrupted.
+        let mut data = vec![ENTRY];
+        data.extend([0x80; u64::MAX_VARINT_SIZE]);
+        match load_all(&data) {
+            Err(Error::Corrupted { .. }) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+
+        // Every truncated dump is corrupted.
+        for size in 0..data.len() {
+            match load_all(&data[..size]) {
+                Err(Error::Corrupted { .. }) => {}
+                x => panic!("unexpected result at {size}: {x:?}"),
+            }
+        }
+        Ok(())
+    }
+}
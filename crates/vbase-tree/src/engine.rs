@@ -1,11 +1,21 @@
 use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::ops::Bound;
+use std::ops::RangeBounds;
 
 use log::info;
 use vbase_engine::engine;
-use vbase_engine::engine::internal;
-use vbase_engine::engine::internal::BucketHandle as _;
+use vbase_engine::engine::Property;
+use vbase_engine::engine::ext;
+use vbase_engine::engine::ext::BucketHandle as _;
 use vbase_engine::env::boxed::Dir;
+use vbase_engine::options::VerifyScope;
+use vbase_engine::util::codec::Decoder;
 use vbase_engine::util::codec::Encoder;
+use vbase_engine::util::rate_limiter::RateLimiter;
+use vbase_engine::util::span;
 use vbase_engine::util::sync::Arc;
 use vbase_engine::util::sync::Mutex;
 use vbase_engine::util::sync::atomic::AtomicU64;
@@ -13,17 +23,26 @@ use vbase_engine::util::sync::atomic::Ordering::Relaxed;
 
 use crate::Error;
 use crate::Result;
+use crate::data::Value;
+use crate::data::Vid;
 use crate::data::WriteBatch;
+use crate::data::WriteBatchIter;
 use crate::data::WriteRecord;
+use crate::dump;
 use crate::file::RootDir;
 use crate::manifest::BucketDesc;
 use crate::manifest::Desc;
 use crate::manifest::Edit;
 use crate::manifest::Manifest;
 use crate::manifest::ManifestWriter;
+use crate::memtable::MemBucketIter;
+use crate::memtable::MemTable;
 
 const NAME: &str = "Tree";
 
+/// The preallocated size of the memtable.
+const MEM_TABLE_SIZE: usize = 4 << 20;
+
 #[derive(Debug)]
 pub struct Bucket(Arc<BucketHandle>);
 
@@ -32,7 +51,7 @@ impl engine::Bucket for Bucket {
     type Writer<'a> = Writer<'a>;
 }
 
-impl internal::Bucket for Bucket {
+impl ext::Bucket for Bucket {
     type Handle = BucketHandle;
 
     fn open(handle: Arc<Self::Handle>) -> Self {
@@ -56,7 +75,7 @@ impl BucketHandle {
     }
 }
 
-impl internal::BucketHandle for BucketHandle {
+impl ext::BucketHandle for BucketHandle {
     fn id(&self) -> u64 {
         self.id
     }
@@ -67,35 +86,216 @@ impl internal::BucketHandle for BucketHandle {
 }
 
 pub struct Reader<'a> {
-    buf: &'a [u8],
+    engine: &'a EngineHandle,
+    id: u64,
+    lsn: u64,
+}
+
+impl<'a> Reader<'a> {
+    /// Gets the value for `id`.
+    ///
+    /// Returns `Ok(None)` if `id` does not exist or has been deleted.
+    pub fn get(&self, id: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(bucket) = self.engine.mem.bucket(self.id) else {
+            return Ok(None);
+        };
+        // Versions of the same id are ordered by LSN in descending order, so
+        // the first one found is the newest visible version.
+        let mut iter = bucket.iter();
+        iter.seek(&Vid::new(id, self.lsn));
+        match iter.next() {
+            Some((vid, Value::Value(value))) if vid.id == id => Ok(Some(value.to_vec())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns an iterator over all values in id order.
+    pub fn iter(&self) -> Iter<'_> {
+        self.range(..)
+    }
+
+    /// Returns an iterator over values with ids in `range`, in id order.
+    pub fn range<'b>(&'b self, range: impl RangeBounds<&'b [u8]>) -> Iter<'b> {
+        let Some(bucket) = self.engine.mem.bucket(self.id) else {
+            return Iter::empty(self.lsn);
+        };
+        let mut iter = Iter {
+            iter: Some(bucket.iter()),
+            lsn: self.lsn,
+            end: range.end_bound().cloned(),
+            last: None,
+        };
+        if let Some(mem) = &mut iter.iter {
+            match range.start_bound() {
+                Bound::Included(&start) => mem.seek(&Vid::new(start, self.lsn)),
+                Bound::Excluded(&start) => {
+                    mem.seek(&Vid::new(start, 0));
+                    iter.last = Some(start);
+                }
+                Bound::Unbounded => mem.seek(&Vid::MIN),
+            }
+        }
+        iter
+    }
+
+    /// Writes a logical dump of all values to `w`.
+    ///
+    /// The dump contains the values visible to the reader in id order, and
+    /// can be loaded into another bucket with [`Writer::load`].
+    pub fn dump<W: Write>(&self, w: W) -> Result<()> {
+        dump::dump(self.iter(), w)
+    }
+}
+
+/// An iterator over the values of a bucket in id order.
+///
+/// The iterator returns the newest version of each id visible to the reader,
+/// and skips ids that have been deleted.
+pub struct Iter<'a> {
+    iter: Option<MemBucketIter<'a>>,
+    lsn: u64,
+    end: Bound<&'a [u8]>,
+    /// The id of the last version returned or skipped.
+    last: Option<&'a [u8]>,
+}
+
+impl<'a> Iter<'a> {
+    fn empty(lsn: u64) -> Self {
+        Self {
+            iter: None,
+            lsn,
+            end: Bound::Unbounded,
+            last: None,
+        }
+    }
+
+    fn before_end(&self, id: &[u8]) -> bool {
+        match self.end {
+            Bound::Included(end) => id <= end,
+            Bound::Excluded(end) => id < end,
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (vid, value) = self.iter.as_mut()?.next()?;
+            // Versions of the same id are ordered by LSN in descending order,
+            // so only the first visible one matters.
+            if vid.lsn > self.lsn || self.last == Some(vid.id) {
+                continue;
+            }
+            if !self.before_end(vid.id) {
+                self.iter = None;
+                return None;
+            }
+            self.last = Some(vid.id);
+            if let Value::Value(value) = value {
+                return Some(Ok((vid.id.to_vec(), value.to_vec())));
+            }
+        }
+    }
 }
 
-impl<'a> internal::Reader<'a> for Reader<'a> {
-    fn new(_: u64) -> Self {
-        todo!()
+impl<'a> ext::Reader<'a> for Reader<'a> {
+    type Engine = EngineHandle;
+
+    fn new(engine: &'a EngineHandle, id: u64, lsn: u64) -> Self {
+        Self { engine, id, lsn }
     }
 }
 
-pub struct Writer<'a>(WriteBatch<'a>);
+pub struct Writer<'a> {
+    batch: WriteBatch<'a>,
+    max_id_size: usize,
+    max_value_size: usize,
+}
 
 impl<'a> Writer<'a> {
     fn new(id: u64, buf: &'a mut Vec<u8>) -> Self {
+        Self::with_limits(id, buf, MAX_ID_SIZE, MAX_VALUE_SIZE)
+    }
+
+    fn with_limits(
+        id: u64,
+        buf: &'a mut Vec<u8>,
+        max_id_size: usize,
+        max_value_size: usize,
+    ) -> Self {
         buf.encode_varint(id);
-        Self(WriteBatch::new(buf))
+        Self {
+            batch: WriteBatch::new(buf),
+            max_id_size,
+            max_value_size,
+        }
     }
 
-    pub fn put(&mut self, id: &[u8], value: &[u8]) -> &mut Self {
-        self.0.add(WriteRecord::Value(id, value));
-        self
+    /// Puts a value for `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if `id` is larger than
+    /// [`MAX_ID_SIZE`] or `value` is larger than [`MAX_VALUE_SIZE`].
+    pub fn put(&mut self, id: &[u8], value: &[u8]) -> Result<&mut Self> {
+        check_size("id", id, self.max_id_size)?;
+        check_size("value", value, self.max_value_size)?;
+        self.batch.add(WriteRecord::Value(id, value));
+        Ok(self)
     }
 
-    pub fn delete(&mut self, id: &[u8]) -> &mut Self {
-        self.0.add(WriteRecord::Tombstone(id));
-        self
+    /// Deletes the value for `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if `id` is larger than
+    /// [`MAX_ID_SIZE`].
+    pub fn delete(&mut self, id: &[u8]) -> Result<&mut Self> {
+        check_size("id", id, self.max_id_size)?;
+        self.batch.add(WriteRecord::Tombstone(id));
+        Ok(self)
     }
+
+    /// Puts values in a dump written by [`Reader::dump`].
+    ///
+    /// Values are added until their ids and values take `max_size` bytes or
+    /// more, so that a large dump can be written in multiple batches by
+    /// passing the same `&mut r` to writers of later batches. Returns true
+    /// if the whole dump has been loaded. Existing values with other ids are
+    /// kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Corrupted`] if the dump is malformed.
+    pub fn load<R: Read>(&mut self, r: R, max_size: usize) -> Result<bool> {
+        dump::load(r, max_size, |id, value| self.put(id, value).map(|_| ()))
+    }
+}
+
+/// The maximum size of an id in bytes.
+///
+/// The limit applies to all buckets, since buckets have no options yet.
+pub const MAX_ID_SIZE: usize = 64 << 10;
+
+/// The maximum size of a value in bytes.
+///
+/// The limit applies to all buckets, since buckets have no options yet.
+pub const MAX_VALUE_SIZE: usize = 1 << 30;
+
+fn check_size(what: &str, data: &[u8], max_size: usize) -> Result<()> {
+    if data.len() > max_size {
+        return Err(Error::InvalidArgument(format!(
+            "{what} size {} exceeds the maximum size {max_size}",
+            data.len()
+        )));
+    }
+    Ok(())
 }
 
-impl<'a> internal::Writer<'a> for Writer<'a> {
+impl<'a> ext::Writer<'a> for Writer<'a> {
     fn new(id: u64, buf: &'a mut Vec<u8>) -> Self {
         Self::new(id, buf)
     }
@@ -107,10 +307,11 @@ impl engine::Engine for Engine {
     type Bucket = Bucket;
 }
 
-impl internal::Engine for Engine {
+impl ext::Engine for Engine {
     type Handle = EngineHandle;
 
     const NAME: &str = NAME;
+    const API_VERSION: u32 = ext::API_VERSION;
 
     fn open(id: u64, dir: Dir) -> Result<Self::Handle> {
         EngineHandle::open(id, dir)
@@ -126,10 +327,22 @@ pub struct EngineHandle {
     buckets: Mutex<HashMap<String, Arc<BucketHandle>>>,
 
     manifest: Mutex<ManifestWriter>,
+
+    mem: MemTable,
+    /// The last LSN written to the memtable.
+    last_lsn: AtomicU64,
+}
+
+impl EngineHandle {
+    /// Returns the approximate number of bytes used by the memtable.
+    pub fn memtable_size(&self) -> usize {
+        self.mem.allocated_size()
+    }
 }
 
 impl EngineHandle {
     fn open(engine_id: u64, dir: Dir) -> Result<Self> {
+        span!("open_engine", id = engine_id);
         let root = RootDir::new(dir);
 
         // Load the current manifest.
@@ -141,17 +354,19 @@ impl EngineHandle {
             None => Desc::default(),
         };
 
+        let mem = MemTable::new(MEM_TABLE_SIZE);
         let mut buckets = HashMap::new();
         for (&id, bucket) in &desc.buckets {
             let handle = BucketHandle::new(id, engine_id);
             buckets.insert(bucket.name.clone(), handle.into());
+            mem.add_bucket(id);
         }
 
         // Switch to a new manifest.
         let last_id = desc.last_id + 1;
         let manifest = root.create_manifest(last_id).and_then(|file| {
             desc.last_id = last_id;
-            ManifestWriter::open(desc, file)
+            ManifestWriter::open(last_id, desc, file)
         })?;
         root.switch_current(last_id)?;
 
@@ -162,6 +377,9 @@ impl EngineHandle {
                 root.delete_manifest(id)?;
             }
         }
+        for name in list.temp_files {
+            root.delete_temp_file(&name)?;
+        }
 
         Ok(Self {
             id: engine_id,
@@ -169,6 +387,8 @@ impl EngineHandle {
             next_id: AtomicU64::new(last_id + 1),
             buckets: Mutex::new(buckets),
             manifest: Mutex::new(manifest),
+            mem,
+            last_lsn: AtomicU64::new(0),
         })
     }
 
@@ -180,17 +400,58 @@ impl EngineHandle {
         let mut manifest = self.manifest.lock().unwrap();
         manifest.write(edit)?;
         if manifest.should_switch_file() {
+            // Keep the previous manifest until CURRENT points to the new one,
+            // so that we can always recover from CURRENT after a crash.
+            let prev_id = manifest.id();
             let id = self.next_id();
+            span!("switch_manifest", from = prev_id, to = id);
             let file = self.root.create_manifest(id)?;
             manifest.switch_file(id, file)?;
             self.root.switch_current(id)?;
-            self.root.delete_manifest(id)?;
+            self.root.delete_manifest(prev_id)?;
         }
         Ok(())
     }
+
+    fn verify_manifest(&self, limiter: &RateLimiter) -> Result<()> {
+        self.verify_manifest_from(self.current_manifest(), limiter)
+    }
+
+    /// Verifies the manifest `(id, size)`, or the current one if it has been
+    /// switched in the meantime.
+    fn verify_manifest_from(
+        &self,
+        (mut id, mut size): (u64, u64),
+        limiter: &RateLimiter,
+    ) -> Result<()> {
+        loop {
+            match self.root.open_manifest(id) {
+                Ok(file) => return Manifest::verify(file, size, limiter),
+                // The manifest has been switched and deleted after the lock
+                // was released, so retry with the current one.
+                Err(Error::Io(e)) if e.kind() == ErrorKind::NotFound => {
+                    let current = self.current_manifest();
+                    if current.0 == id {
+                        return Err(Error::Io(e));
+                    }
+                    (id, size) = current;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the id of the current manifest and the size written so far.
+    ///
+    /// The current manifest is being written, so only the part that has been
+    /// written so far can be verified.
+    fn current_manifest(&self) -> (u64, u64) {
+        let manifest = self.manifest.lock().unwrap();
+        (manifest.id(), manifest.size())
+    }
 }
 
-impl internal::EngineHandle for EngineHandle {
+impl ext::EngineHandle for EngineHandle {
     fn id(&self) -> u64 {
         self.id
     }
@@ -199,15 +460,30 @@ impl internal::EngineHandle for EngineHandle {
         NAME
     }
 
-    fn write(&self, lsn: u64, batch: &[u8]) {
-        todo!()
+    fn write(&self, lsn: u64, mut batch: &[u8]) {
+        span!("apply", lsn, size = batch.len());
+        // The batch consists of records of each writer, which starts with the
+        // bucket id and ends with an end marker.
+        while !batch.is_empty() {
+            let id = batch.decode_varint();
+            let mut iter = WriteBatchIter::new(batch);
+            let bucket = self.mem.bucket(id);
+            for record in iter.by_ref() {
+                if let Some(bucket) = &bucket {
+                    let (vid, value) = record.into_version(lsn);
+                    bucket.add(vid, value);
+                }
+            }
+            batch = iter.remaining();
+        }
+        self.last_lsn.fetch_max(lsn, Relaxed);
     }
 
     fn last_lsn(&self) -> u64 {
-        0
+        self.last_lsn.load(Relaxed)
     }
 
-    fn bucket(&self, name: &str) -> Result<Arc<dyn internal::BucketHandle>> {
+    fn bucket(&self, name: &str) -> Result<Arc<dyn ext::BucketHandle>> {
         let buckets = self.buckets.lock().unwrap();
         let Some(bucket) = buckets.get(name) else {
             return Err(Error::NotExist(format!("bucket {name}")));
@@ -215,7 +491,7 @@ impl internal::EngineHandle for EngineHandle {
         Ok(bucket.clone())
     }
 
-    fn create_bucket(&self, name: &str) -> Result<Arc<dyn internal::BucketHandle>> {
+    fn create_bucket(&self, name: &str) -> Result<Arc<dyn ext::BucketHandle>> {
         let mut buckets = self.buckets.lock().unwrap();
         if buckets.contains_key(name) {
             return Err(Error::Exists(format!("bucket {name}")));
@@ -224,11 +500,14 @@ impl internal::EngineHandle for EngineHandle {
         let id = self.next_id();
         let desc = BucketDesc::new(name.into());
         info!("create bucket {name} with id {id}");
-        let mut edit = Edit::default();
-        edit.last_id = id;
+        let mut edit = Edit {
+            last_id: id,
+            ..Default::default()
+        };
         edit.add_buckets.insert(id, desc);
         self.update_manifest(edit)?;
 
+        self.mem.add_bucket(id);
         let bucket = Arc::new(BucketHandle::new(id, self.id));
         buckets.insert(name.into(), bucket.clone());
         Ok(bucket)
@@ -245,7 +524,342 @@ impl internal::EngineHandle for EngineHandle {
         edit.delete_buckets.push(bucket.id());
         self.update_manifest(edit)?;
 
+        // Stop applying writes from existing handles of the bucket.
+        self.mem.remove_bucket(bucket.id());
         buckets.remove(name);
         Ok(())
     }
+
+    fn verify_files(&self, scope: &VerifyScope, limiter: &RateLimiter) -> Result<()> {
+        match scope {
+            VerifyScope::All | VerifyScope::Manifests => self.verify_manifest(limiter),
+            VerifyScope::Journals => Ok(()),
+            VerifyScope::Bucket { bucket_id, .. } => {
+                let buckets = self.buckets.lock().unwrap();
+                if !buckets.values().any(|b| b.id == *bucket_id) {
+                    return Err(Error::NotExist(format!("bucket {bucket_id}")));
+                }
+                // Buckets do not have files yet.
+                Ok(())
+            }
+        }
+    }
+
+    fn backup_files(&self, copy: &mut dyn FnMut(&str, &[u8]) -> Result<()>) -> Result<()> {
+        // Hold the lock so that the manifest is not switched while it is
+        // being copied.
+        let manifest = self.manifest.lock().unwrap();
+        self.root.backup(manifest.id(), manifest.size(), copy)
+    }
+
+    fn property(&self, name: &str) -> Option<Property> {
+        let value = match name {
+            "memtable-bytes" => self.mem.allocated_size() as u64,
+            "num-buckets" => self.buckets.lock().unwrap().len() as u64,
+            _ => {
+                let level = name.strip_prefix("num-files-at-level")?.parse().ok()?;
+                let manifest = self.manifest.lock().unwrap();
+                manifest
+                    .desc()
+                    .buckets
+                    .values()
+                    .flat_map(|b| b.ranges.values())
+                    .filter(|r| r.level == level)
+                    .count() as u64
+            }
+        };
+        Some(Property::Int(value))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.mem.allocated_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message as _;
+    use vbase_engine::engine::ext::EngineHandle as _;
+    use vbase_engine::engine::ext::Reader as _;
+    use vbase_engine::engine::testkit;
+    use vbase_engine::env::SequentialFileWriter as _;
+    use vbase_engine::env::boxed::Env;
+    use vbase_engine::file::journal::FileWriter;
+
+    use super::*;
+    use crate::manifest::FORMAT_VERSION;
+
+    const PATH: &str = "test";
+
+    impl testkit::TestEngine for Engine {
+        fn put(id: u64, key: &[u8], value: &[u8], buf: &mut Vec<u8>) {
+            Writer::new(id, buf).put(key, value).unwrap();
+        }
+
+        fn get(engine: &EngineHandle, id: u64, key: &[u8], lsn: u64) -> Result<Option<Vec<u8>>> {
+            Reader::new(engine, id, lsn).get(key)
+        }
+    }
+
+    #[test]
+    fn test_conformance() -> Result<()> {
+        testkit::run::<Engine>()
+    }
+
+    #[test]
+    fn test_switch_manifest() -> Result<()> {
+        let env = Env::test()?;
+        let engine = EngineHandle::open(1, env.create_dir(PATH)?)?;
+        let first_id = engine.manifest.lock().unwrap().id();
+        for i in 0..100 {
+            engine.create_bucket(&format!("bucket-{i}"))?;
+        }
+        let last_id = engine.manifest.lock().unwrap().id();
+        assert_ne!(first_id, last_id);
+        assert_eq!(engine.root.list()?.manifests, [last_id].into());
+
+        // Verifying a deleted manifest moves on to the current one.
+        let limiter = RateLimiter::new(0);
+        engine.verify_manifest_from((first_id, 0), &limiter)?;
+        drop(engine);
+
+        let engine = EngineHandle::open(1, env.open_dir(PATH)?)?;
+        for i in 0..100 {
+            engine.bucket(&format!("bucket-{i}"))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read() -> Result<()> {
+        let env = Env::test()?;
+        let engine = EngineHandle::open(1, env.create_dir(PATH)?)?;
+        let a = engine.create_bucket("a")?.id();
+        let b = engine.create_bucket("b")?.id();
+
+        let mut buf = Vec::new();
+        Writer::new(a, &mut buf)
+            .put(b"1", b"a1")?
+            .put(b"2", b"a2")?;
+        Writer::new(b, &mut buf).put(b"1", b"b1")?;
+        // Writes to unknown buckets are ignored.
+        Writer::new(b + 100, &mut buf).put(b"1", b"c1")?;
+        engine.write(1, &buf);
+        buf.clear();
+        Writer::new(a, &mut buf).put(b"1", b"a3")?.delete(b"2")?;
+        engine.write(2, &buf);
+        assert_eq!(engine.last_lsn(), 2);
+
+        let get = |id, lsn, key: &[u8]| Reader::new(&engine, id, lsn).get(key);
+        assert_eq!(get(a, 0, b"1")?, None);
+        assert_eq!(get(a, 1, b"1")?, Some(b"a1".to_vec()));
+        assert_eq!(get(a, 1, b"2")?, Some(b"a2".to_vec()));
+        assert_eq!(get(a, 2, b"1")?, Some(b"a3".to_vec()));
+        assert_eq!(get(a, 2, b"2")?, None);
+        assert_eq!(get(a, 2, b"3")?, None);
+        assert_eq!(get(b, 2, b"1")?, Some(b"b1".to_vec()));
+        assert_eq!(get(b, 2, b"2")?, None);
+        assert_eq!(get(b + 100, 2, b"1")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_bucket() -> Result<()> {
+        let env = Env::test()?;
+        let (a, b) = {
+            let engine = EngineHandle::open(1, env.create_dir(PATH)?)?;
+            let a = engine.create_bucket("a")?.id();
+            let mut buf = Vec::new();
+            Writer::new(a, &mut buf).put(b"1", b"a1")?;
+            engine.write(1, &buf);
+            engine.delete_bucket("a")?;
+
+            // Reads and writes with the deleted id see nothing.
+            assert_eq!(Reader::new(&engine, a, 1).get(b"1")?, None);
+            engine.write(2, &buf);
+            assert_eq!(Reader::new(&engine, a, 2).get(b"1")?, None);
+
+            // A bucket with the same name gets a new id.
+            let b = engine.create_bucket("a")?.id();
+            assert!(b > a);
+            engine.delete_bucket("a")?;
+            (a, b)
+        };
+
+        // Ids are not reused after reopening either.
+        let engine = EngineHandle::open(1, env.open_dir(PATH)?)?;
+        let c = engine.create_bucket("a")?.id();
+        assert!(c > a && c > b);
+        Ok(())
+    }
+
+    #[test]
+    fn test_range() -> Result<()> {
+        use Bound::*;
+
+        let env = Env::test()?;
+        let engine = EngineHandle::open(1, env.create_dir(PATH)?)?;
+        let a = engine.create_bucket("a")?.id();
+
+        let mut buf = Vec::new();
+        Writer::new(a, &mut buf)
+            .put(b"1", b"1")?
+            .put(b"2", b"2")?
+            .put(b"3", b"3")?
+            .put(b"4", b"4")?;
+        engine.write(1, &buf);
+        buf.clear();
+        Writer::new(a, &mut buf)
+            .put(b"1", b"11")?
+            .delete(b"3")?
+            .put(b"5", b"5")?;
+        engine.write(2, &buf);
+
+        let scan = |lsn, start: Bound<&str>, end: Bound<&str>| {
+            let reader = Reader::new(&engine, a, lsn);
+            let range = (start.map(str::as_bytes), end.map(str::as_bytes));
+            reader.range(range).collect::<Result<Vec<_>>>()
+        };
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(scan(0, Unbounded, Unbounded)?, pairs(&[]));
+        assert_eq!(
+            scan(1, Unbounded, Unbounded)?,
+            pairs(&[("1", "1"), ("2", "2"), ("3", "3"), ("4", "4")])
+        );
+        assert_eq!(
+            scan(2, Unbounded, Unbounded)?,
+            pairs(&[("1", "11"), ("2", "2"), ("4", "4"), ("5", "5")])
+        );
+        assert_eq!(
+            scan(2, Included("1"), Excluded("4"))?,
+            pairs(&[("1", "11"), ("2", "2")])
+        );
+        assert_eq!(
+            scan(2, Excluded("1"), Included("4"))?,
+            pairs(&[("2", "2"), ("4", "4")])
+        );
+        assert_eq!(scan(2, Included("6"), Unbounded)?, pairs(&[]));
+        assert_eq!(Reader::new(&engine, a, 2).iter().count(), 4);
+        assert_eq!(Reader::new(&engine, a + 100, 2).iter().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump() -> Result<()> {
+        let env = Env::test()?;
+        let engine = EngineHandle::open(1, env.create_dir(PATH)?)?;
+        let a = engine.create_bucket("a")?.id();
+        let b = engine.create_bucket("b")?.id();
+
+        let mut buf = Vec::new();
+        Writer::new(a, &mut buf)
+            .put(b"1", b"1")?
+            .put(b"2", b"2")?
+            .put(b"4", b"4")?;
+        Writer::new(b, &mut buf).put(b"3", b"3")?;
+        engine.write(1, &buf);
+        buf.clear();
+        Writer::new(a, &mut buf).delete(b"1")?;
+        engine.write(2, &buf);
+
+        let mut dump = Vec::new();
+        Reader::new(&engine, a, 2).dump(&mut dump)?;
+        // Load one value per batch.
+        let mut r = dump.as_slice();
+        let mut lsn = 2;
+        loop {
+            buf.clear();
+            let done = Writer::new(b, &mut buf).load(&mut r, 1)?;
+            lsn += 1;
+            engine.write(lsn, &buf);
+            if done {
+                break;
+            }
+        }
+        assert_eq!(lsn, 5);
+        let values = Reader::new(&engine, b, lsn)
+            .iter()
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            values,
+            [
+                (b"2".to_vec(), b"2".to_vec()),
+                (b"3".to_vec(), b"3".to_vec()),
+                (b"4".to_vec(), b"4".to_vec())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_size() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut writer = Writer::with_limits(1, &mut buf, 4, 8);
+        writer.put(&[0; 4], &[0; 8])?;
+        writer.delete(&[0; 4])?;
+        match writer.put(&[0; 5], &[]) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+        match writer.delete(&[0; 5]) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+        match writer.put(&[], &[0; 9]) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_newer_format_version() -> Result<()> {
+        let env = Env::test()?;
+        drop(EngineHandle::open(1, env.create_dir(PATH)?)?);
+
+        // Switch to a manifest written by a newer version.
+        let root = RootDir::new(env.open_dir(PATH)?);
+        let desc = Desc {
+            format_version: FORMAT_VERSION + 1,
+            ..Default::default()
+        };
+        let id = root.read_current()?.unwrap() + 1;
+        let mut file = root.create_manifest(id).map(FileWriter::new)?;
+        file.write(desc.encode_to_vec())?;
+        root.switch_current(id)?;
+        match EngineHandle::open(1, env.open_dir(PATH)?) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_crash_during_switch() -> Result<()> {
+        let env = Env::test()?;
+        let engine = EngineHandle::open(1, env.create_dir(PATH)?)?;
+        engine.create_bucket("bucket")?;
+        let id = engine.manifest.lock().unwrap().id();
+        drop(engine);
+
+        // Simulate a crash after the new manifest is created but before
+        // CURRENT is switched.
+        let dir = env.open_dir(PATH)?;
+        let root = RootDir::new(env.open_dir(PATH)?);
+        root.create_manifest(id + 100)?.write_exact(b"garbage")?;
+        std::mem::forget(dir.create_temp_file("CURRENT")?);
+
+        let engine = EngineHandle::open(1, dir)?;
+        engine.bucket("bucket")?;
+        let list = engine.root.list()?;
+        assert_eq!(list.manifests.len(), 1);
+        assert!(!list.manifests.contains(&(id + 100)));
+        assert!(list.temp_files.is_empty());
+        Ok(())
+    }
 }
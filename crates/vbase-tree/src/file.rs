@@ -1,9 +1,12 @@
 use std::collections::BTreeSet;
 use std::io::ErrorKind;
 
+use vbase_engine::env::SequentialFileWriter as _;
 use vbase_engine::env::boxed::Dir;
 use vbase_engine::env::boxed::SequentialFile;
 use vbase_engine::env::boxed::SequentialFileWriter;
+use vbase_engine::env::boxed::TempFile;
+use vbase_engine::util::failpoint;
 
 use crate::Result;
 use crate::error::Corrupted;
@@ -11,6 +14,7 @@ use crate::error::Corrupted;
 #[derive(Default)]
 pub(crate) struct FileSet {
     pub(crate) manifests: BTreeSet<u64>,
+    pub(crate) temp_files: BTreeSet<String>,
 }
 
 pub(crate) struct RootDir {
@@ -18,7 +22,6 @@ pub(crate) struct RootDir {
 }
 
 impl RootDir {
-    const TEMP: &str = "TEMP";
     const CURRENT: &str = "CURRENT";
 
     pub(crate) fn new(dir: Dir) -> Self {
@@ -28,6 +31,9 @@ impl RootDir {
     pub(crate) fn list(&self) -> Result<FileSet> {
         let mut list = FileSet::default();
         let names = self.dir.list()?;
+        for name in names.iter().filter(|name| TempFile::is_temp(name)) {
+            list.temp_files.insert(name.clone());
+        }
         for name in names.iter().filter_map(|name| Name::parse(name)) {
             let _ = match name {
                 Name::Manifest(id) => list.manifests.insert(id),
@@ -52,8 +58,10 @@ impl RootDir {
 
     pub(crate) fn switch_current(&self, id: u64) -> Result<()> {
         let name = Name::manifest(id);
-        self.dir.write_file(Self::TEMP, name.as_bytes())?;
-        self.dir.rename_file(Self::TEMP, Self::CURRENT)?;
+        let mut file = self.dir.create_temp_file(Self::CURRENT)?;
+        file.write_exact(name.as_bytes())?;
+        failpoint::check("tree::current::before_persist")?;
+        file.persist(Self::CURRENT)?;
         Ok(())
     }
 
@@ -71,6 +79,25 @@ impl RootDir {
         let name = Name::manifest(id);
         self.dir.delete_file(&name).map_err(Into::into)
     }
+
+    /// Passes the first `size` bytes of manifest `id` and a CURRENT pointing
+    /// to it to `copy`.
+    pub(crate) fn backup(
+        &self,
+        id: u64,
+        size: u64,
+        copy: &mut dyn FnMut(&str, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let name = Name::manifest(id);
+        let mut data = self.dir.read_file(&name)?;
+        data.truncate(size as usize);
+        copy(&name, &data)?;
+        copy(Self::CURRENT, name.as_bytes())
+    }
+
+    pub(crate) fn delete_temp_file(&self, name: &str) -> Result<()> {
+        self.dir.delete_file(name).map_err(Into::into)
+    }
 }
 
 enum Name {
@@ -7,8 +7,15 @@ pub use error::Result;
 mod engine;
 pub use engine::Bucket;
 pub use engine::Engine;
+pub use engine::EngineHandle;
+pub use engine::Iter;
+pub use engine::MAX_ID_SIZE;
+pub use engine::MAX_VALUE_SIZE;
+pub use engine::Reader;
+pub use engine::Writer;
 
 mod data;
+mod dump;
 mod file;
 mod manifest;
 mod memtable;
@@ -5,10 +5,22 @@ use vbase_engine::env::boxed::SequentialFile;
 use vbase_engine::env::boxed::SequentialFileWriter;
 use vbase_engine::file::journal::File;
 use vbase_engine::file::journal::FileWriter;
+use vbase_engine::util::rate_limiter::RateLimiter;
 
+use crate::Error;
 use crate::Result;
 use crate::error::Corrupted;
 
+/// The current on-disk format version of the engine.
+///
+/// Bump it when the format changes in a way that older versions can not
+/// read. Manifests written before versioning decode as version 0, which is
+/// the same format as version 1.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+/// The first record of a manifest file.
+///
+/// The record is read as an [`Edit`], so the tags must match.
 #[derive(Message)]
 #[derive(Clone, Eq, PartialEq)]
 pub(crate) struct Desc {
@@ -16,11 +28,14 @@ pub(crate) struct Desc {
     pub(crate) last_id: u64,
     #[prost(tag = "2", map = "uint64, message")]
     pub(crate) buckets: HashMap<u64, BucketDesc>,
+    #[prost(tag = "5", uint32)]
+    pub(crate) format_version: u32,
 }
 
 impl Desc {
     fn merge(&mut self, edit: Edit) {
         self.last_id = self.last_id.max(edit.last_id);
+        self.format_version = self.format_version.max(edit.format_version);
         self.buckets.extend(edit.add_buckets);
         for id in edit.delete_buckets {
             if self.buckets.remove(&id).is_none() {
@@ -47,6 +62,8 @@ pub(crate) struct Edit {
     pub(crate) delete_buckets: Vec<u64>,
     #[prost(tag = "4", map = "uint64, message")]
     pub(crate) update_buckets: HashMap<u64, BucketEdit>,
+    #[prost(tag = "5", uint32)]
+    pub(crate) format_version: u32,
 }
 
 #[derive(Message)]
@@ -116,9 +133,27 @@ impl Manifest {
         while let Some(edit) = this.read()? {
             desc.merge(edit);
         }
+        if desc.format_version > FORMAT_VERSION {
+            return Err(Error::InvalidArgument(format!(
+                "{} was created by a newer version with format {}, \
+                but this version only supports format {FORMAT_VERSION}",
+                this.file.path(),
+                desc.format_version,
+            )));
+        }
         Ok(desc)
     }
 
+    /// Verifies the first `size` bytes of the file.
+    pub(crate) fn verify(file: SequentialFile, size: u64, limiter: &RateLimiter) -> Result<()> {
+        let mut this = Self {
+            file: File::new(file).limit(size),
+        };
+        limiter.request(size as usize);
+        while this.read()?.is_some() {}
+        Ok(())
+    }
+
     /// Reads an [`Edit`] from the file.
     pub(crate) fn read(&mut self) -> Result<Option<Edit>> {
         match self.file.read()? {
@@ -132,6 +167,7 @@ impl Manifest {
 
 /// A manifest file writer.
 pub(crate) struct ManifestWriter {
+    id: u64,
     desc: Desc,
     file: FileWriter,
     /// The initial size of the current file.
@@ -141,10 +177,12 @@ pub(crate) struct ManifestWriter {
 }
 
 impl ManifestWriter {
-    const MIN_FILE_SIZE: u64 = 1024 * 1024;
+    // Use a smaller size in tests to exercise file switching.
+    const MIN_FILE_SIZE: u64 = if cfg!(test) { 1024 } else { 1024 * 1024 };
 
-    pub(crate) fn open(desc: Desc, file: SequentialFileWriter) -> Result<Self> {
+    pub(crate) fn open(id: u64, desc: Desc, file: SequentialFileWriter) -> Result<Self> {
         let mut this = Self {
+            id,
             desc,
             file: FileWriter::new(file),
             init_size: 0,
@@ -153,6 +191,21 @@ impl ManifestWriter {
         Ok(this)
     }
 
+    /// Returns the id of the current file.
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the size of the current file.
+    pub(crate) fn size(&self) -> u64 {
+        self.file.size()
+    }
+
+    /// Returns the current description.
+    pub(crate) fn desc(&self) -> &Desc {
+        &self.desc
+    }
+
     /// Writes an edit to the file.
     pub(crate) fn write(&mut self, edit: Edit) -> Result<()> {
         self.file.write(edit.encode_to_vec())?;
@@ -162,6 +215,7 @@ impl ManifestWriter {
     }
 
     fn init_file(&mut self) -> Result<()> {
+        self.desc.format_version = FORMAT_VERSION;
         self.file.write(self.desc.encode_to_vec())?;
         self.file.sync()?;
         self.init_size = self.file.size();
@@ -170,6 +224,7 @@ impl ManifestWriter {
 
     /// Switches to the given file.
     pub(crate) fn switch_file(&mut self, id: u64, file: SequentialFileWriter) -> Result<()> {
+        self.id = id;
         self.desc.last_id = id;
         self.file = FileWriter::new(file);
         self.init_file()
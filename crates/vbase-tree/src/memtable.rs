@@ -24,6 +24,8 @@ impl MemTable {
     /// Creates a new [`MemTable`] of the given size.
     pub(crate) fn new(size: usize) -> Self {
         let arena = Arena::new(size);
+        // Huge pages are only a hint, so the memtable works without them.
+        let _ = arena.advise_huge_pages();
         let buckets = arena.alloc_value(BucketVec::new());
         Self {
             arena,
@@ -40,6 +42,11 @@ impl MemTable {
         })
     }
 
+    /// Returns the approximate number of bytes used by the memtable.
+    pub(crate) fn allocated_size(&self) -> usize {
+        self.arena.allocated_size()
+    }
+
     /// Adds a new bucket with the given id.
     pub(crate) fn add_bucket(&self, id: u64) {
         let old = self.load_buckets();
@@ -53,6 +60,22 @@ impl MemTable {
         buckets.sort();
         self.store_buckets(buckets);
     }
+
+    /// Removes the bucket with the given id.
+    ///
+    /// The data of the bucket stays in the arena, so buckets that have been
+    /// got before remain valid.
+    pub(crate) fn remove_bucket(&self, id: u64) {
+        let old = self.load_buckets();
+        if old.find(id).is_none() {
+            return;
+        }
+        let mut buckets = BucketVec::with(self.arena.alloc_slice(old.len() - 1));
+        for bucket in old.iter().filter(|b| b.id != id) {
+            buckets.push(bucket.clone());
+        }
+        self.store_buckets(buckets);
+    }
 }
 
 impl MemTable {
@@ -164,6 +187,13 @@ pub(crate) struct MemBucketIter<'a> {
     iter: SkipListIter<'a, Vid<'a>, Value<'a>>,
 }
 
+impl<'a> MemBucketIter<'a> {
+    /// Positions the iterator to the first version >= `vid`.
+    pub(crate) fn seek(&mut self, vid: &Vid<'a>) {
+        self.iter.seek(vid);
+    }
+}
+
 impl<'a> Iterator for MemBucketIter<'a> {
     type Item = (Vid<'a>, Value<'a>);
 
@@ -207,6 +237,12 @@ mod tests {
             bucket.add(K2, V2);
         }
 
+        // Remove buckets
+        for &id in &ids {
+            mem.remove_bucket(id);
+            assert!(mem.bucket(id).is_none());
+        }
+
         // The orginal buckets should still be valid.
         for bucket in buckets {
             let mut iter = bucket.iter();
@@ -2,6 +2,7 @@ use std::alloc;
 use std::alloc::Layout;
 use std::alloc::LayoutError;
 use std::alloc::handle_alloc_error;
+use std::io;
 use std::mem;
 use std::ptr;
 
@@ -90,6 +91,36 @@ impl<const ALIGN: usize> Buffer<ALIGN> {
     pub const fn size(&self) -> usize {
         self.size
     }
+
+    /// Advises the kernel to back the buffer with transparent huge pages.
+    ///
+    /// This is only a hint to reduce TLB misses for large buffers. Only the
+    /// pages entirely within the buffer are advised, so it has no effect on
+    /// buffers smaller than a page. It does nothing on platforms other than
+    /// Linux.
+    pub fn advise_huge_pages(&self) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+            let page_size = usize::try_from(page_size).map_err(|_| io::Error::last_os_error())?;
+            let start = self.ptr.addr().next_multiple_of(page_size);
+            let end = (self.ptr.addr() + self.size) / page_size * page_size;
+            if start < end {
+                let ret = unsafe {
+                    // SAFETY: the range is page-aligned and within the buffer.
+                    libc::madvise(
+                        self.ptr.with_addr(start).cast(),
+                        end - start,
+                        libc::MADV_HUGEPAGE,
+                    )
+                };
+                if ret != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<const ALIGN: usize> Buffer<ALIGN> {
@@ -1,4 +1,5 @@
 use std::alloc::Layout;
+use std::io;
 use std::mem::MaybeUninit;
 use std::ptr::NonNull;
 
@@ -96,6 +97,14 @@ impl<const ALIGN: usize> Arena<ALIGN> {
         NonNull::slice_from_raw_parts(self.alloc(size).cast(), len)
     }
 
+    /// Advises the kernel to back the preallocated buffer with transparent
+    /// huge pages.
+    ///
+    /// See [`Buffer::advise_huge_pages`] for details.
+    pub fn advise_huge_pages(&self) -> io::Result<()> {
+        self.buf.advise_huge_pages()
+    }
+
     /// Returns the approximate number of bytes allocated from the arena.
     pub fn allocated_size(&self) -> usize {
         self.offset.load(Relaxed).try_into().unwrap_or(usize::MAX)
@@ -150,6 +159,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_huge_pages() {
+        // Too small to contain a whole page.
+        let arena = Arena::<8>::new(64);
+        arena.advise_huge_pages().unwrap();
+        let arena = Arena::<8>::new(0);
+        arena.advise_huge_pages().unwrap();
+    }
+
     #[test]
     fn test_zst() {
         const ALIGN: usize = 1;
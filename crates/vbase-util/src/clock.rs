@@ -0,0 +1,124 @@
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use crate::sync::atomic::AtomicU64;
+use crate::sync::atomic::Ordering::Relaxed;
+
+/// A source of time.
+///
+/// Components that depend on time should read it from a clock, so that tests
+/// can control the time with a [`MockClock`].
+pub trait Clock: fmt::Debug + Send + Sync + 'static {
+    /// Returns the current wall-clock time.
+    fn now(&self) -> SystemTime;
+
+    /// Returns the current monotonic time.
+    fn monotonic_now(&self) -> Instant;
+
+    /// Returns the current wall-clock time in seconds since the Unix epoch.
+    ///
+    /// Returns 0 if the time is before the epoch.
+    fn unix_secs(&self) -> u64 {
+        self.now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+
+    /// Blocks the current thread for `duration`.
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock that reads the system time.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn monotonic_now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when it is advanced.
+///
+/// Both the wall-clock and the monotonic time start from the time the clock
+/// is created, and move forward together. Sleeping advances the clock instead
+/// of blocking.
+#[derive(Debug)]
+pub struct MockClock {
+    system: SystemTime,
+    instant: Instant,
+    /// The elapsed time in nanoseconds.
+    elapsed: AtomicU64,
+}
+
+impl MockClock {
+    /// Creates a clock that starts at the current system time.
+    pub fn new() -> Self {
+        Self::with_time(SystemTime::now())
+    }
+
+    /// Creates a clock that starts at `time`.
+    pub fn with_time(time: SystemTime) -> Self {
+        Self {
+            system: time,
+            instant: Instant::now(),
+            elapsed: AtomicU64::new(0),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.elapsed.fetch_add(nanos, Relaxed);
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed.load(Relaxed))
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        self.system + self.elapsed()
+    }
+
+    fn monotonic_now(&self) -> Instant {
+        self.instant + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::with_time(SystemTime::UNIX_EPOCH);
+        let start = clock.monotonic_now();
+        assert_eq!(clock.unix_secs(), 0);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(clock.unix_secs(), 10);
+        assert_eq!(clock.monotonic_now() - start, Duration::from_secs(10));
+        assert_eq!(clock.monotonic_now(), clock.monotonic_now());
+        clock.sleep(Duration::from_secs(5));
+        assert_eq!(clock.unix_secs(), 15);
+    }
+}
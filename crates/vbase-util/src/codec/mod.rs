@@ -53,7 +53,7 @@ macro_rules! impl_int {
             fn encode_to<E: Encoder>(self, enc: &mut E) {
                 let mut v = self;
                 while v >= 0x80 {
-                    enc.put(v as u8);
+                    enc.put(v as u8 | 0x80);
                     v >>= 7;
                 }
                 enc.put(v as u8);
@@ -255,4 +255,29 @@ mod tests {
         assert_eq!(Varint::size(u64::MAX), u64::MAX_VARINT_SIZE);
         assert_eq!(Varint::size(usize::MAX), usize::MAX_VARINT_SIZE);
     }
+
+    #[test]
+    fn test_varint_encoding() {
+        // Every byte but the last has the continuation bit set, including
+        // bytes whose bit 7 is not set in the value.
+        let cases: &[(u64, &[u8])] = &[
+            (0, &[0x00]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x80, 0x01]),
+            (0x100, &[0x80, 0x02]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x4000, &[0x80, 0x80, 0x01]),
+            (1 << 35, &[0x80, 0x80, 0x80, 0x80, 0x80, 0x01]),
+        ];
+        for &(value, bytes) in cases {
+            let mut buf = Vec::new();
+            buf.encode_varint(value);
+            assert_eq!(buf, bytes, "{value:#x}");
+            assert_eq!(buf.len(), Varint::size(value));
+            assert_eq!(buf.as_slice().decode_varint::<u64>(), value);
+        }
+        let mut buf = Vec::new();
+        buf.encode_varint(u64::MAX);
+        assert_eq!(buf.as_slice().decode_varint::<u64>(), u64::MAX);
+    }
 }
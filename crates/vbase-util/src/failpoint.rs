@@ -0,0 +1,74 @@
+//! Failpoints for fault injection in tests.
+//!
+//! A failpoint is a named point in the code that calls [`check`]. With the
+//! `failpoints` feature, tests can configure a failpoint to return an error
+//! or panic when it is reached, which exercises a specific crash window.
+//! Without the feature, [`check`] always succeeds and compiles to nothing.
+//!
+//! Failpoints are configured per thread, so that tests running in parallel
+//! do not interfere with each other. A failpoint only triggers in the thread
+//! that configures it.
+
+use std::io;
+
+/// What to do when a failpoint is reached.
+#[cfg(feature = "failpoints")]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Action {
+    /// Returns an I/O error from the failpoint.
+    Error,
+    /// Panics at the failpoint.
+    Panic,
+}
+
+#[cfg(feature = "failpoints")]
+std::thread_local! {
+    static FAILPOINTS: std::cell::RefCell<std::collections::HashMap<String, Action>> =
+        Default::default();
+}
+
+/// Configures failpoint `name` to take `action` in the current thread.
+#[cfg(feature = "failpoints")]
+pub fn set(name: &str, action: Action) {
+    FAILPOINTS.with_borrow_mut(|map| map.insert(name.into(), action));
+}
+
+/// Removes the configuration of failpoint `name` in the current thread.
+#[cfg(feature = "failpoints")]
+pub fn remove(name: &str) {
+    FAILPOINTS.with_borrow_mut(|map| map.remove(name));
+}
+
+/// Evaluates failpoint `name`.
+///
+/// Returns an error or panics if the failpoint is configured to do so.
+#[inline]
+pub fn check(name: &str) -> io::Result<()> {
+    #[cfg(feature = "failpoints")]
+    match FAILPOINTS.with_borrow(|map| map.get(name).copied()) {
+        Some(Action::Error) => return Err(io::Error::other(format!("failpoint {name}"))),
+        Some(Action::Panic) => panic!("failpoint {name}"),
+        None => {}
+    }
+    #[cfg(not(feature = "failpoints"))]
+    let _ = name;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "failpoints"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        const NAME: &str = "test";
+        check(NAME).unwrap();
+        set(NAME, Action::Error);
+        assert!(check(NAME).is_err());
+        std::thread::spawn(|| check(NAME).unwrap()).join().unwrap();
+        set(NAME, Action::Panic);
+        assert!(std::panic::catch_unwind(|| check(NAME)).is_err());
+        remove(NAME);
+        check(NAME).unwrap();
+    }
+}
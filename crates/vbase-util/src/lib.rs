@@ -2,10 +2,14 @@ pub mod alloc;
 pub mod arena;
 pub mod bytes;
 pub mod cell;
+pub mod clock;
 pub mod codec;
 pub mod crc32;
+pub mod failpoint;
+pub mod rate_limiter;
 pub mod skip_list;
 pub mod spmc_queue;
+pub mod trace;
 
 pub use concurrent::*;
 
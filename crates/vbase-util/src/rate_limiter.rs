@@ -0,0 +1,103 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::sync::Arc;
+use crate::sync::Mutex;
+
+/// A rate limiter to pace the throughput of background work.
+///
+/// The limiter tracks the total number of bytes requested since it was
+/// created, and sleeps the requesting thread if the average rate exceeds the
+/// limit.
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    clock: Arc<dyn Clock>,
+    state: Mutex<State>,
+}
+
+struct State {
+    start: Instant,
+    bytes: u64,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter with the given limit.
+    ///
+    /// A zero `bytes_per_sec` means no limit.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self::with_clock(bytes_per_sec, Arc::new(SystemClock))
+    }
+
+    /// Creates a rate limiter that reads the time from `clock` and sleeps
+    /// with it.
+    pub fn with_clock(bytes_per_sec: u64, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            bytes_per_sec,
+            state: Mutex::new(State {
+                start: clock.monotonic_now(),
+                bytes: 0,
+            }),
+            clock,
+        }
+    }
+
+    /// Creates a rate limiter without limit.
+    pub fn unlimited() -> Self {
+        Self::new(0)
+    }
+
+    /// Requests `bytes` from the limiter.
+    ///
+    /// This function sleeps until the rate falls under the limit.
+    pub fn request(&self, bytes: usize) {
+        if self.bytes_per_sec == 0 {
+            return;
+        }
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            state.bytes = state.bytes.saturating_add(bytes as u64);
+            let expected = Duration::from_secs_f64(state.bytes as f64 / self.bytes_per_sec as f64);
+            let elapsed = self.clock.monotonic_now() - state.start;
+            expected.saturating_sub(elapsed)
+        };
+        if !wait.is_zero() {
+            self.clock.sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn test() {
+        let limiter = RateLimiter::unlimited();
+        let start = Instant::now();
+        limiter.request(usize::MAX);
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        let limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        limiter.request(50);
+        limiter.request(50);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = Arc::new(MockClock::new());
+        let limiter = RateLimiter::with_clock(1000, clock.clone());
+        let start = clock.monotonic_now();
+        limiter.request(500);
+        limiter.request(500);
+        assert_eq!(clock.monotonic_now() - start, Duration::from_secs(1));
+        // Time that has passed is not waited for again.
+        clock.advance(Duration::from_secs(1));
+        limiter.request(1000);
+        assert_eq!(clock.monotonic_now() - start, Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,33 @@
+//! Spans for tracing.
+//!
+//! With the `tracing` feature, [`span!`](crate::span) enters an info-level
+//! [`tracing`] span until the end of the enclosing block. Without the
+//! feature, it compiles to nothing, so spans cost nothing by default.
+//!
+//! [`tracing`]: https://docs.rs/tracing
+
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing;
+
+/// Enters a span until the end of the enclosing block.
+///
+/// This takes the same arguments as [`tracing::info_span!`].
+///
+/// [`tracing::info_span!`]: https://docs.rs/tracing/latest/tracing/macro.info_span.html
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! span {
+    ($($arg:tt)*) => {
+        let _span = $crate::trace::tracing::info_span!($($arg)*).entered();
+    };
+}
+
+/// Enters a span until the end of the enclosing block.
+///
+/// This does nothing without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! span {
+    ($($arg:tt)*) => {};
+}
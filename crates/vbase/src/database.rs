@@ -6,11 +6,21 @@ use vbase_util::sync::Arc;
 #[allow(unused_imports, reason = "for doc comments")]
 use crate::Error;
 
+use crate::Bucket;
 use crate::Engine;
+use crate::EngineRef;
+use crate::Identity;
 use crate::Options;
+use crate::Property;
 use crate::Result;
+use crate::Snapshot;
+use crate::VerifyOptions;
+use crate::VerifyScope;
 use crate::WriteBatch;
 use crate::WriteOptions;
+use crate::WriteStallStats;
+use crate::backup::BackupEngine;
+use crate::tree;
 
 /// A database builder.
 pub struct Builder(options::Builder);
@@ -27,6 +37,20 @@ impl Builder {
         self
     }
 
+    /// Registers an engine and restores it from the archive.
+    ///
+    /// If the engine has been archived by [`Database::archive_engine`], the
+    /// last archived one is restored with all its data. If the engine has
+    /// already been restored, this is the same as [`Self::engine`].
+    ///
+    /// Opening fails with [`Error::Exists`] if the engine has been created
+    /// again after it was archived, or with [`Error::NotExist`] if the engine
+    /// has never been archived.
+    pub fn restore_engine<E: Engine>(mut self) -> Self {
+        self.0 = self.0.restore_engine::<E>();
+        self
+    }
+
     /// If true, returns an error if the database already exists.
     ///
     /// Conflicts with [`Self::error_if_not_exist`].
@@ -78,6 +102,14 @@ impl Default for Builder {
 }
 
 /// A multi-model embedded database.
+///
+/// A database can not be used by a process forked after it is opened, where
+/// operations return [`Error::Poisoned`]. Dropping it there leaves its threads
+/// and files to the parent.
+///
+/// A database is also poisoned if a write fails after it is appended to the
+/// journal, for example when the journal can not be synchronized. The failed
+/// write is recovered when the database is reopened.
 #[derive(Clone, Debug)]
 pub struct Database(Arc<Core>);
 
@@ -93,10 +125,111 @@ impl Database {
     }
 
     /// Writes a batch to the database.
+    ///
+    /// # Errors
+    ///
+    /// - Returns [`Error::InvalidArgument`] if the batch is larger than
+    ///   [`Options::max_batch_size`] or does not fit in a journal record.
+    /// - Returns [`Error::Busy`] if engines use more memory than
+    ///   [`Options::write_stop_size`].
+    /// - Returns [`Error::Poisoned`] if the database is used by a forked
+    ///   process or poisoned by a failed write.
     pub fn write(&self, batch: &WriteBatch, options: &WriteOptions) -> Result<()> {
         self.0.write(batch, options)
     }
 
+    /// Returns the value of property `name`, or `None` if it does not exist.
+    ///
+    /// Database properties are:
+    ///
+    /// - `vbase.id`: the id of the database, see [`Self::identity`].
+    /// - `vbase.last-lsn`: the LSN of the last visible write.
+    /// - `vbase.num-journals`: the number of journal files.
+    /// - `vbase.memtable-bytes`: the memory used by all engines.
+    /// - `vbase.delayed-writes` and `vbase.rejected-writes`: the number of
+    ///   stalled writes, see [`Self::write_stall_stats`].
+    /// - `vbase.journal-queue-depth`: the number of journal writes queued in
+    ///   the background, see [`Options::journal_write_queue`].
+    ///
+    /// Engine properties are named `vbase.{engine}.{name}`, where `{engine}`
+    /// is the lowercase name of the engine. The tree engine has:
+    ///
+    /// - `vbase.tree.memtable-bytes`: the memory used by the memtable.
+    /// - `vbase.tree.num-buckets`: the number of buckets.
+    /// - `vbase.tree.num-files-at-level<N>`: the number of files at level N.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the files needed by the property can not be
+    /// listed.
+    pub fn property(&self, name: &str) -> Result<Option<Property>> {
+        self.0.property(name)
+    }
+
+    /// Returns the identity of the database.
+    ///
+    /// The id stays the same across opens, so it identifies the database
+    /// directory. See [`Identity`] for details.
+    pub fn identity(&self) -> &Identity {
+        self.0.identity()
+    }
+
+    /// Returns statistics of writes stalled by memory usage.
+    pub fn write_stall_stats(&self) -> WriteStallStats {
+        self.0.write_stall_stats()
+    }
+
+    /// Returns an empty batch, reusing one from the pool if possible.
+    ///
+    /// See [`Options::batch_pool_size`] for details.
+    pub fn batch(&self) -> WriteBatch {
+        self.0.batch()
+    }
+
+    /// Returns a batch to the pool for reuse.
+    ///
+    /// The batch is cleared if it is kept, or dropped if the pool is full.
+    pub fn recycle_batch(&self, batch: WriteBatch) {
+        self.0.recycle_batch(batch)
+    }
+
+    /// Returns a reader for the bucket.
+    ///
+    /// The reader sees all writes that have been published when it is
+    /// created.
+    ///
+    /// # Errors
+    ///
+    /// - Returns [`Error::InvalidArgument`] if the engine of the bucket is not
+    ///   opened in this database.
+    /// - Returns [`Error::Poisoned`] if the database is used by a forked
+    ///   process or poisoned by a failed write.
+    pub fn read<B: Bucket>(&self, bucket: &B) -> Result<B::Reader<'_>> {
+        self.0.read(bucket)
+    }
+
+    /// Takes a snapshot of the database.
+    ///
+    /// The snapshot sees all writes that have been published when it is
+    /// taken. Readers created from it see the same state across buckets.
+    ///
+    /// Readers can not be created from it in a forked process, see
+    /// [`Snapshot::read`].
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        self.0.snapshot()
+    }
+
+    /// Returns a typed reference to engine `E`.
+    ///
+    /// The reference exposes APIs that are specific to the engine.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if `E` is not registered.
+    pub fn engine<E: Engine>(&self) -> Result<EngineRef<'_, E>> {
+        self.0.engine::<E>()
+    }
+
     /// Gets a bucket from the engine if it exists.
     ///
     /// # Errors
@@ -110,31 +243,234 @@ impl Database {
     ///
     /// # Errors
     ///
-    /// Returns [`Error::Exists`] if `name` already exists.
+    /// - Returns [`Error::Exists`] if `name` already exists.
+    /// - Returns [`Error::Poisoned`] if the database is used by a forked
+    ///   process or poisoned by a failed write.
     pub fn create_bucket<E: Engine>(&self, name: &str) -> Result<E::Bucket> {
         self.0.create_bucket::<E>(name)
     }
 
     /// Deletes a bucket from the engine.
     ///
+    /// Existing handles of the bucket read nothing afterwards, and writes to
+    /// them are ignored. A new bucket with the same name gets a new id, so it
+    /// never sees data of the deleted one.
+    ///
     /// # Errors
     ///
-    /// Returns [`Error::NotExist`] if `name` does not exist.
+    /// - Returns [`Error::NotExist`] if `name` does not exist.
+    /// - Returns [`Error::Poisoned`] if the database is used by a forked
+    ///   process or poisoned by a failed write.
     pub fn delete_bucket<E: Engine>(&self, name: &str) -> Result<()> {
         self.0.delete_bucket::<E>(name)
     }
+
+    /// Archives engine `E`.
+    ///
+    /// This function removes the engine from the database and renames its
+    /// directory to `archived-engine-{id}`, so that its data is kept and can
+    /// be restored with [`Builder::restore_engine`]. Existing buckets of the
+    /// engine can still be read until the database is closed, but writes to
+    /// them return [`Error::InvalidArgument`].
+    ///
+    /// Journals with writes that the engine has not persisted are kept until
+    /// the engine is restored.
+    ///
+    /// An archived engine is created again if it is still registered the next
+    /// time the database is opened.
+    ///
+    /// # Errors
+    ///
+    /// - Returns [`Error::InvalidArgument`] if `E` is not registered.
+    /// - Returns [`Error::Poisoned`] if the database is used by a forked
+    ///   process or poisoned by a failed write.
+    pub fn archive_engine<E: Engine>(&self) -> Result<()> {
+        self.0.archive_engine::<E>()
+    }
+
+    /// Verifies the checksums of files in `scope`.
+    ///
+    /// This function streams through the files without blocking writes. The
+    /// read throughput can be limited with [`VerifyOptions::rate_limit`].
+    ///
+    /// # Errors
+    ///
+    /// - Returns [`Error::Corrupted`] if any file is corrupted.
+    /// - Returns [`Error::NotExist`] if the bucket in `scope` does not exist.
+    /// - Returns [`Error::Poisoned`] if the database is used by a forked
+    ///   process or poisoned by a failed write.
+    pub fn verify_files(&self, scope: &VerifyScope, options: &VerifyOptions) -> Result<()> {
+        self.0.verify_files(scope, options)
+    }
+
+    /// Creates a backup in `engine` and returns its id.
+    ///
+    /// This function copies the manifest, the engine files and the journals
+    /// without blocking writes. Writes that are not yet in the journals when
+    /// they are copied are not in the backup.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Poisoned`] if the database is used by a forked
+    /// process or poisoned by a failed write.
+    pub fn backup(&self, engine: &BackupEngine) -> Result<u64> {
+        engine.create_backup(&self.0)
+    }
+
+    /// Closes the database.
+    ///
+    /// Unlike dropping the database, this function synchronizes the journal
+    /// first, so that all writes survive a crash afterwards, and reports
+    /// errors instead of ignoring them. The handle is dropped even if an
+    /// error is returned. If there are other clones of the handle, the
+    /// database remains open until they are all dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Poisoned`] if the database is used by a forked
+    /// process or poisoned by a failed write.
+    pub fn close(self) -> Result<()> {
+        self.0.sync()
+    }
+}
+
+/// Shortcuts for single updates to tree buckets.
+impl Database {
+    /// Puts a value for `id` in the bucket.
+    ///
+    /// This is equivalent to writing a batch with a single put.
+    ///
+    /// # Errors
+    ///
+    /// See [`tree::Writer::put`] and [`Self::write`].
+    pub fn put(
+        &self,
+        bucket: &tree::Bucket,
+        id: &[u8],
+        value: &[u8],
+        options: &WriteOptions,
+    ) -> Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.bucket(bucket).put(id, value)?;
+        self.write(&batch, options)
+    }
+
+    /// Deletes the value for `id` in the bucket.
+    ///
+    /// This is equivalent to writing a batch with a single delete.
+    ///
+    /// # Errors
+    ///
+    /// See [`tree::Writer::delete`] and [`Self::write`].
+    pub fn delete(&self, bucket: &tree::Bucket, id: &[u8], options: &WriteOptions) -> Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.bucket(bucket).delete(id)?;
+        self.write(&batch, options)
+    }
+
+    /// Gets the value for `id` in the bucket.
+    ///
+    /// This is equivalent to getting the value from a new reader.
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::read`].
+    pub fn get(&self, bucket: &tree::Bucket, id: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.read(bucket)?.get(id)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    use vbase_core::engine::ext;
+    use vbase_env::boxed::Dir;
+    use vbase_env::boxed::Env;
+    use vbase_env::boxed::TempFile;
+    use vbase_util::clock::Clock as _;
+    use vbase_util::clock::MockClock;
+    use vbase_util::codec::Encode;
+    use vbase_util::crc32::checksum;
+    use vbase_util::crc32::checksum_combined;
+    use vbase_util::failpoint;
+    use vbase_util::failpoint::Action;
+    use vbase_util::rate_limiter::RateLimiter;
+
+    use crate::ArchiveSink;
     use crate::Builder;
     use crate::Database;
     use crate::Error;
     use crate::Options;
+    use crate::Property;
     use crate::Result;
+    use crate::VerifyOptions;
+    use crate::VerifyScope;
+    use crate::WriteBatch;
+    use crate::WriteOptions;
+    use crate::backup::BackupEngine;
     use crate::tree::Engine;
 
-    const PATH: &'static str = "test";
+    const PATH: &str = "test";
+
+    macro_rules! golden {
+        ($version:literal, $($name:literal),*) => {
+            &[$((
+                $name,
+                include_bytes!(concat!("../goldens/", $version, "/", $name)),
+            )),*]
+        };
+    }
+    /// Databases written by each format version.
+    ///
+    /// Each one has bucket "a", deleted bucket "b", and three journal records.
+    /// Add a new version when the format changes, and never modify the
+    /// existing ones.
+    const GOLDENS: &[&[(&str, &[u8])]] = &[
+        golden!(
+            "v0",
+            "MANIFEST",
+            "journal-1",
+            "engine-1/CURRENT",
+            "engine-1/manifest-1"
+        ),
+        golden!(
+            "v1",
+            "MANIFEST",
+            "journal-1",
+            "engine-1/CURRENT",
+            "engine-1/manifest-1"
+        ),
+        golden!(
+            "v2",
+            "MANIFEST",
+            "journal-1",
+            "engine-1/CURRENT",
+            "engine-1/manifest-1"
+        ),
+        golden!(
+            "v3",
+            "MANIFEST",
+            "journal-1",
+            "engine-1/CURRENT",
+            "engine-1/manifest-1"
+        ),
+    ];
+
+    /// Writes golden files to `PATH` in `env`.
+    fn write_golden(env: &Env, files: &[(&str, &[u8])]) -> Result<()> {
+        for (name, data) in files {
+            let (dir, name) = match name.rsplit_once('/') {
+                Some((dir, name)) => (env.create_dir(&format!("{PATH}/{dir}"))?, name),
+                None => (env.create_dir(PATH)?, *name),
+            };
+            dir.write_file(name, data)?;
+        }
+        Ok(())
+    }
 
     fn test_database() -> Result<Database> {
         let options = Options::test()?;
@@ -181,6 +517,99 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_steal_stale_lock() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?.env(env.clone());
+        let locked = Database::open(PATH, options.clone())?;
+        // The lock is held by a live process.
+        match Database::open(PATH, options.clone().steal_stale_lock(true)) {
+            Err(Error::Locked(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        drop(locked);
+
+        // Pretend that the lock is left behind by a dead process, such as a
+        // lock on NFS or one inherited by a child that outlives its parent.
+        let dir = env.open_dir(PATH)?;
+        let _stale = dir.lock_file("LOCK")?;
+        dir.write_file("LOCK.info", format!("{} 1\n", u32::MAX).as_bytes())?;
+        match Database::open(PATH, options.clone()) {
+            Err(Error::Locked(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        let _stolen = Database::open(PATH, options.steal_stale_lock(true))?;
+        assert_eq!(
+            dir.read_file("LOCK.info")?,
+            format!("{} 2\n", std::process::id()).as_bytes()
+        );
+        Ok(())
+    }
+
+    /// Passes `value` to `f` in a forked child process, and returns it with
+    /// whether `f` succeeds in the child.
+    #[cfg(target_os = "linux")]
+    fn fork_with<T>(value: T, f: impl FnOnce(T) -> Result<()>) -> (T, bool) {
+        // SAFETY: the child only runs `f` and exits.
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(value)));
+            let code = if matches!(result, Ok(Ok(()))) { 0 } else { 1 };
+            // SAFETY: exit without running destructors of the parent.
+            unsafe { libc::_exit(code) };
+        }
+        let mut status = 0;
+        // SAFETY: `pid` is a child of this process.
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        (
+            value,
+            libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0,
+        )
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fork() -> Result<()> {
+        let options = Options::test()?
+            .background_publish(true)
+            .journal_write_queue(16);
+        let db = Builder::new()
+            .engine::<Engine>()
+            .open(PATH, options.clone())?;
+        let bucket = db.create_bucket::<Engine>("a")?;
+        db.put(&bucket, b"1", b"1", &WriteOptions::default())?;
+
+        let (db, ok) = fork_with(db, |db| {
+            let poisoned = |result: Result<()>| match result {
+                Err(Error::Poisoned(..)) => Ok(()),
+                x => Err(Error::InvalidArgument(format!("unexpected result: {x:?}"))),
+            };
+            poisoned(db.read(&bucket).map(|_| ()))?;
+            poisoned(db.snapshot().read(&bucket).map(|_| ()))?;
+            poisoned(db.put(&bucket, b"2", b"2", &WriteOptions::default()))?;
+            poisoned(db.create_bucket::<Engine>("b").map(|_| ()))?;
+            poisoned(db.verify_files(&VerifyScope::All, &VerifyOptions::default()))?;
+            // Closing the last handle leaves the threads and files alone.
+            poisoned(db.close())
+        });
+        assert!(ok);
+
+        // The parent still holds the lock and writes as usual.
+        match Database::open(PATH, options.clone()) {
+            Err(Error::Locked(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        db.put(&bucket, b"3", b"3", &WriteOptions::default())?;
+        db.close()?;
+        let db = Builder::new().engine::<Engine>().open(PATH, options)?;
+        let bucket = db.bucket::<Engine>("a")?;
+        assert_eq!(db.get(&bucket, b"1")?, Some(b"1".to_vec()));
+        assert_eq!(db.get(&bucket, b"2")?, None);
+        assert_eq!(db.get(&bucket, b"3")?, Some(b"3".to_vec()));
+        Ok(())
+    }
+
     #[test]
     fn test_unregistered_engine() -> Result<()> {
         let options = Options::test()?;
@@ -224,4 +653,1130 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_max_batch_size() -> Result<()> {
+        let options = Options::test()?.max_batch_size(10);
+        let db = Builder::new().engine::<Engine>().open(PATH, options)?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        let mut batch = WriteBatch::new();
+        batch.bucket(&bucket).put(&[0; 16], &[0; 16])?;
+        assert!(batch.size() > 10);
+        match db.write(&batch, &WriteOptions::default()) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_version() -> Result<()> {
+        // An engine built against another version of the engine API.
+        struct OtherEngine;
+
+        impl crate::Engine for OtherEngine {
+            type Bucket = crate::tree::Bucket;
+        }
+
+        impl ext::Engine for OtherEngine {
+            type Handle = crate::tree::EngineHandle;
+
+            const NAME: &str = "other";
+            const API_VERSION: u32 = ext::API_VERSION + 1;
+
+            fn open(id: u64, dir: Dir) -> Result<Self::Handle> {
+                <Engine as ext::Engine>::open(id, dir)
+            }
+        }
+
+        let options = Options::test()?;
+        match Builder::new().engine::<OtherEngine>().open(PATH, options) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_engine() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?.env(env.clone());
+        let db = Builder::new()
+            .engine::<Engine>()
+            .open(PATH, options.clone())?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        db.put(&bucket, b"a", b"1", &WriteOptions::default())?;
+        db.archive_engine::<Engine>()?;
+        match db.create_bucket::<Engine>("test") {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        // Existing buckets can be read but not written.
+        assert_eq!(db.get(&bucket, b"a")?, Some(b"1".to_vec()));
+        match db.put(&bucket, b"b", b"2", &WriteOptions::default()) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        drop(db);
+        let dir = env.open_dir(PATH)?;
+        assert!(dir.list()?.contains(&"archived-engine-1".into()));
+
+        // The archived engine is not required to open the database, and the
+        // journals are kept for it.
+        Database::open(PATH, options.clone())?;
+        // An interrupted restore is reverted.
+        dir.rename_dir("archived-engine-1", "engine-1")?;
+        Database::open(PATH, options.clone())?;
+        assert!(dir.list()?.contains(&"archived-engine-1".into()));
+
+        // The archived engine is restored with its data.
+        let restore = || Builder::new().restore_engine::<Engine>();
+        let db = restore().open(PATH, options.clone())?;
+        let bucket = db.bucket::<Engine>("test")?;
+        assert_eq!(db.get(&bucket, b"a")?, Some(b"1".to_vec()));
+        assert_eq!(db.get(&bucket, b"b")?, None);
+        drop(db);
+        // Restoring a restored engine only opens it.
+        let db = restore().open(PATH, options.clone())?;
+        db.archive_engine::<Engine>()?;
+        drop(db);
+
+        // A new engine is created if it is registered again.
+        let db = Builder::new()
+            .engine::<Engine>()
+            .open(PATH, options.clone())?;
+        match db.bucket::<Engine>("test") {
+            Err(Error::NotExist(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        drop(db);
+        // The archived engine can not be restored over the new engine.
+        match restore().open(PATH, options.clone()) {
+            Err(Error::Exists(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        match restore().open("empty", options) {
+            Err(Error::NotExist(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_files() -> Result<()> {
+        let db = test_database()?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        let options = VerifyOptions::new();
+        db.verify_files(&VerifyScope::All, &options)?;
+        db.verify_files(&VerifyScope::Journals, &options)?;
+        db.verify_files(&VerifyScope::Manifests, &options)?;
+        let scope = VerifyScope::bucket(&bucket);
+        db.verify_files(&scope, &options)?;
+        db.delete_bucket::<Engine>("test")?;
+        match db.verify_files(&scope, &options) {
+            Err(Error::NotExist(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_map() -> Result<()> {
+        let options = Options::test()?
+            .journal_dir("journals")
+            .engine_dir::<Engine>("engines");
+        {
+            let db = Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())?;
+            db.create_bucket::<Engine>("test")?;
+            db.verify_files(&VerifyScope::All, &VerifyOptions::new())?;
+        }
+        {
+            let db = Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())?;
+            db.bucket::<Engine>("test")?;
+        }
+        // The engine can not be found without the mapping.
+        let options = options.engine_dir::<Engine>(PATH);
+        match Builder::new().engine::<Engine>().open(PATH, options) {
+            Err(Error::Io(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_env() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?.journal_env(env.clone());
+        Database::open(PATH, options.clone())?;
+        assert!(env.open_dir(PATH)?.list()?.contains(&"journal-1".into()));
+        let options = options.journal_dir("journals");
+        Database::open(PATH, options)?;
+        assert!(
+            env.open_dir("journals")?
+                .list()?
+                .contains(&"journal-1".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_rotation() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?.env(env.clone()).journal_file_size(1);
+        let open = || {
+            Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())
+        };
+        let journals = || -> Result<Vec<String>> {
+            let list = env.open_dir(PATH)?.list()?;
+            Ok(list
+                .into_iter()
+                .filter(|x| x.starts_with("journal-"))
+                .collect())
+        };
+        {
+            let db = open()?;
+            let bucket = db.create_bucket::<Engine>("test")?;
+            for key in [b"a", b"b", b"c"] {
+                let mut batch = WriteBatch::new();
+                batch.bucket(&bucket).put(key, key)?;
+                db.write(&batch, &WriteOptions::default())?;
+            }
+            // Each write fills up a journal.
+            assert_eq!(journals()?, ["journal-1", "journal-2", "journal-3"]);
+        }
+        let db = open()?;
+        let bucket = db.bucket::<Engine>("test")?;
+        let reader = db.read(&bucket)?;
+        for key in [b"a", b"b", b"c"] {
+            assert_eq!(reader.get(key)?, Some(key.to_vec()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_close() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?.env(env).journal_write_queue(16);
+        let open = || {
+            Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())
+        };
+        let db = open()?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        let mut batch = WriteBatch::new();
+        batch.bucket(&bucket).put(b"a", b"1")?;
+        db.write(&batch, &WriteOptions::default())?;
+        db.close()?;
+
+        let db = open()?;
+        let bucket = db.bucket::<Engine>("test")?;
+        assert_eq!(db.read(&bucket)?.get(b"a")?, Some(b"1".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_engine() -> Result<()> {
+        let db = test_database()?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        let engine = db.engine::<Engine>()?;
+        let size = engine.memtable_size();
+        db.put(&bucket, b"a", b"1", &WriteOptions::default())?;
+        assert!(engine.memtable_size() > size);
+
+        let db = Builder::new().open("empty", Options::test()?)?;
+        match db.engine::<Engine>() {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_pool() -> Result<()> {
+        let options = Options::test()?.batch_pool_size(1);
+        let db = Builder::new().engine::<Engine>().open(PATH, options)?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        for (id, value) in [(b"a", b"1"), (b"b", b"2")] {
+            let mut batch = db.batch();
+            assert_eq!(batch.size(), 0);
+            batch.bucket(&bucket).put(id, value)?;
+            db.write(&batch, &WriteOptions::default())?;
+            db.recycle_batch(batch);
+        }
+        let reader = db.read(&bucket)?;
+        assert_eq!(reader.get(b"a")?, Some(b"1".to_vec()));
+        assert_eq!(reader.get(b"b")?, Some(b"2".to_vec()));
+
+        // A cleared batch writes nothing.
+        let mut batch = WriteBatch::new();
+        batch.bucket(&bucket).delete(b"a")?;
+        batch.clear();
+        db.write(&batch, &WriteOptions::default())?;
+        assert_eq!(db.get(&bucket, b"a")?, Some(b"1".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_identity() -> Result<()> {
+        let env = Env::test()?;
+        let created = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let clock = Arc::new(MockClock::with_time(created));
+        let options = Options::test()?.env(env.clone()).clock(clock.clone());
+        let identity = {
+            let db = Builder::new().open(PATH, options.clone())?;
+            let identity = db.identity().clone();
+            assert_eq!(identity.id().len(), 36);
+            assert_eq!(identity.created(), created);
+            assert_eq!(identity.version(), env!("CARGO_PKG_VERSION"));
+            assert!(identity.engines().is_empty());
+            identity
+        };
+
+        // Only the engines change after reopening.
+        clock.advance(Duration::from_secs(10));
+        let db = Builder::new()
+            .engine::<Engine>()
+            .open(PATH, options.clone())?;
+        assert_eq!(db.identity().id(), identity.id());
+        assert_eq!(db.identity().created(), created);
+        assert_eq!(db.identity().engines(), ["Tree"]);
+        drop(db);
+
+        // A corrupted file is regenerated with the id that can be read.
+        let dir = env.open_dir(PATH)?;
+        let data = format!("id = {}\ncorrupted", identity.id());
+        dir.write_file("IDENTITY", data.as_bytes())?;
+        let db = Builder::new()
+            .engine::<Engine>()
+            .open(PATH, options.clone())?;
+        assert_eq!(db.identity().id(), identity.id());
+        assert_eq!(db.identity().created(), clock.now());
+        drop(db);
+        assert!(
+            String::from_utf8(dir.read_file("IDENTITY")?)
+                .unwrap()
+                .contains("engines = Tree")
+        );
+        dir.write_file("IDENTITY", b"corrupted")?;
+        let db = Builder::new().engine::<Engine>().open(PATH, options)?;
+        assert_ne!(db.identity().id(), identity.id());
+        drop(db);
+
+        // Another database has a different id.
+        let db = test_database()?;
+        assert_ne!(db.identity().id(), identity.id());
+        Ok(())
+    }
+
+    #[test]
+    fn test_options_file() -> Result<()> {
+        let text = r#"
+            # Comments and blank lines are ignored.
+            journal_dir = "journal # \"dir\""
+            journal_file_size = 1_024 # bytes
+            max_batch_size = "unlimited"
+            background_publish = true
+            trash_retention_ms = 1000
+
+            [engine_dirs]
+            Tree = "tree"
+        "#;
+        let options: Options = text.parse()?;
+        let formatted = options.to_toml();
+        assert!(formatted.contains("journal_dir = \"journal # \\\"dir\\\"\"\n"));
+        assert!(formatted.contains("journal_file_size = 1024\n"));
+        assert!(formatted.contains("max_batch_size = \"unlimited\"\n"));
+        assert!(formatted.contains("background_publish = true\n"));
+        assert!(formatted.contains("trash_retention_ms = 1000\n"));
+        assert!(formatted.contains("[engine_dirs]\n\"Tree\" = \"tree\"\n"));
+        assert_eq!(formatted.parse::<Options>()?.to_toml(), formatted);
+
+        // Other forms of keys, strings and integers.
+        let text = r#"
+            "journal_dir" = '''
+C:\journal
+'''
+            journal_file_size = 0x400
+            info_log_files = +0o12
+            trash_retention_ms = 1_000
+            engine_dirs."Tree" = """\
+                tr\u0065e"""
+        "#;
+        let formatted = text.parse::<Options>()?.to_toml();
+        assert!(formatted.contains("journal_dir = \"C:\\\\journal\\n\"\n"));
+        assert!(formatted.contains("journal_file_size = 1024\n"));
+        assert!(formatted.contains("info_log_files = 10\n"));
+        assert!(formatted.contains("trash_retention_ms = 1000\n"));
+        assert!(formatted.contains("\"Tree\" = \"tree\"\n"));
+        let formatted = "engine_dirs = { Tree = 'tree', Other = '' }"
+            .parse::<Options>()?
+            .to_toml();
+        assert!(formatted.contains("[engine_dirs]\n\"Other\" = \"\"\n\"Tree\" = \"tree\"\n"));
+
+        for text in [
+            "unknown = 1",
+            "journal_file_size = true",
+            "journal_dir = \"dir",
+            "journal_dir = \"\\x\"",
+            "[unknown]",
+            "[engine_dirs]\n[engine_dirs]",
+            "journal_file_size",
+            "journal_file_size = 1 2",
+            "journal_file_size = 1\njournal_file_size = 2",
+            "journal_file_size = -1",
+            "journal_file_size = 01",
+            "journal_file_size = 1__0",
+            // Valid TOML that no option takes.
+            "journal_file_size = 1.5",
+            "journal_file_size = [1]",
+            "trash_retention_ms = 1979-05-27",
+            "[[engine_dirs]]",
+        ] {
+            match text.parse::<Options>() {
+                Err(Error::InvalidArgument(_)) => {}
+                x => panic!("unexpected result for {text:?}: {x:?}"),
+            }
+        }
+
+        // The effective options are written at open, keeping the last two.
+        let env = Env::test()?;
+        let options = Options::test()?.env(env.clone()).journal_file_size(1024);
+        for _ in 0..3 {
+            Builder::new().open(PATH, options.clone())?;
+        }
+        let mut list = env.open_dir(PATH)?.list()?;
+        list.retain(|name| name.starts_with("OPTIONS-"));
+        list.sort();
+        assert_eq!(list, ["OPTIONS-2", "OPTIONS-3"]);
+        let data = env.open_dir(PATH)?.read_file("OPTIONS-3")?;
+        assert_eq!(String::from_utf8(data).unwrap(), options.to_toml());
+        let loaded = Options::from_toml_file(&env.open_dir(PATH)?, "OPTIONS-3")?;
+        assert_eq!(loaded.to_toml(), options.to_toml());
+        Ok(())
+    }
+
+    #[test]
+    fn test_info_log() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?
+            .env(env.clone())
+            .info_log_size(4096)
+            .info_log_files(2);
+        let logs = || -> Result<Vec<String>> {
+            let mut list = env.open_dir(PATH)?.list()?;
+            list.retain(|name| name.starts_with("LOG"));
+            list.sort();
+            Ok(list)
+        };
+        {
+            let db = Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())?;
+            db.create_bucket::<Engine>("test")?;
+        }
+        assert_eq!(logs()?, ["LOG"]);
+        let data = env.open_dir(PATH)?.read_file("LOG")?;
+        let data = String::from_utf8(data).unwrap();
+        assert!(data.contains(" open test with "));
+        assert!(data.contains(" create bucket test in engine Tree\n"));
+
+        // Each open rolls the log, and only the last old logs are kept.
+        for _ in 0..3 {
+            Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())?;
+        }
+        assert_eq!(logs()?, ["LOG", "LOG.2", "LOG.3"]);
+
+        // A full log rolls as well, so each event is in its own file.
+        let db = Builder::new()
+            .engine::<Engine>()
+            .open(PATH, options.info_log_size(1))?;
+        db.delete_bucket::<Engine>("test")?;
+        assert_eq!(logs()?.len(), 3);
+        let data = env.open_dir(PATH)?.read_file("LOG")?;
+        let data = String::from_utf8(data).unwrap();
+        assert!(data.ends_with(" delete bucket test from engine Tree\n"));
+        assert_eq!(data.lines().count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_property() -> Result<()> {
+        let db = test_database()?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        db.put(&bucket, b"a", b"1", &WriteOptions::default())?;
+        let property = |name| db.property(name).map(Option::unwrap);
+        assert_eq!(property("vbase.id")?.to_string(), db.identity().id());
+        assert_eq!(property("vbase.last-lsn")?, Property::Int(1));
+        assert_eq!(property("vbase.num-journals")?, Property::Int(1));
+        assert_eq!(property("vbase.delayed-writes")?, Property::Int(0));
+        assert_eq!(property("vbase.journal-queue-depth")?, Property::Int(0));
+        let Property::Int(size) = property("vbase.memtable-bytes")? else {
+            panic!("memtable-bytes is not an integer");
+        };
+        assert_eq!(size, db.engine::<Engine>()?.memtable_size() as u64);
+        assert_eq!(property("vbase.tree.memtable-bytes")?, Property::Int(size));
+        assert_eq!(property("vbase.tree.num-buckets")?, Property::Int(1));
+        assert_eq!(
+            property("vbase.tree.num-files-at-level0")?,
+            Property::Int(0)
+        );
+        for name in [
+            "last-lsn",
+            "vbase.unknown",
+            "vbase.tree.unknown",
+            "vbase.tree.num-files-at-levelx",
+            "vbase.unknown.memtable-bytes",
+        ] {
+            assert_eq!(db.property(name)?, None, "{name}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_stall() -> Result<()> {
+        let clock = Arc::new(MockClock::new());
+        let options = Options::test()?.clock(clock.clone());
+        let open = |options: Options| Builder::new().engine::<Engine>().open(PATH, options);
+        {
+            let db = open(options.clone().write_slowdown_size(0))?;
+            let bucket = db.create_bucket::<Engine>("test")?;
+            let start = clock.monotonic_now();
+            db.put(&bucket, b"a", b"1", &WriteOptions::default())?;
+            // Writes are delayed with the clock.
+            assert_eq!(clock.monotonic_now() - start, Duration::from_millis(1));
+            let stats = db.write_stall_stats();
+            assert_eq!(stats.delayed_writes(), 1);
+            assert_eq!(stats.delay(), Duration::from_millis(1));
+            assert_eq!(stats.rejected_writes(), 0);
+        }
+
+        // The tree engine does not release memory, so writes past the stop
+        // size are only delayed even after the database is reopened.
+        let options = options.write_slowdown_size(1).write_stop_size(1);
+        for value in [b"2", b"3"] {
+            let db = open(options.clone())?;
+            let bucket = db.bucket::<Engine>("test")?;
+            db.put(&bucket, b"b", value, &WriteOptions::default())?;
+            assert_eq!(db.write_stall_stats().delayed_writes(), 1);
+            assert_eq!(db.write_stall_stats().rejected_writes(), 0);
+        }
+
+        // Writes are rejected if all engines release memory.
+        struct Releasing(crate::tree::EngineHandle);
+
+        impl crate::Engine for Releasing {
+            type Bucket = crate::tree::Bucket;
+        }
+
+        impl ext::Engine for Releasing {
+            type Handle = Self;
+
+            const NAME: &str = "releasing";
+            const API_VERSION: u32 = ext::API_VERSION;
+
+            fn open(id: u64, dir: Dir) -> Result<Self> {
+                <Engine as ext::Engine>::open(id, dir).map(Self)
+            }
+        }
+
+        impl ext::EngineHandle for Releasing {
+            fn id(&self) -> u64 {
+                self.0.id()
+            }
+
+            fn name(&self) -> &str {
+                <Self as ext::Engine>::NAME
+            }
+
+            fn write(&self, lsn: u64, batch: &[u8]) {
+                self.0.write(lsn, batch)
+            }
+
+            fn last_lsn(&self) -> u64 {
+                self.0.last_lsn()
+            }
+
+            fn bucket(&self, name: &str) -> Result<Arc<dyn ext::BucketHandle>> {
+                self.0.bucket(name)
+            }
+
+            fn create_bucket(&self, name: &str) -> Result<Arc<dyn ext::BucketHandle>> {
+                self.0.create_bucket(name)
+            }
+
+            fn delete_bucket(&self, name: &str) -> Result<()> {
+                self.0.delete_bucket(name)
+            }
+
+            fn verify_files(&self, scope: &VerifyScope, limiter: &RateLimiter) -> Result<()> {
+                self.0.verify_files(scope, limiter)
+            }
+
+            fn backup_files(&self, copy: &mut dyn FnMut(&str, &[u8]) -> Result<()>) -> Result<()> {
+                self.0.backup_files(copy)
+            }
+
+            fn memory_usage(&self) -> usize {
+                self.0.memory_usage()
+            }
+
+            fn releases_memory(&self) -> bool {
+                true
+            }
+        }
+
+        let db = Builder::new()
+            .engine::<Releasing>()
+            .open("releasing", options)?;
+        let bucket = db.create_bucket::<Releasing>("test")?;
+        match db.put(&bucket, b"a", b"1", &WriteOptions::default()) {
+            Err(Error::Busy(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        assert_eq!(db.write_stall_stats().rejected_writes(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory() -> Result<()> {
+        let options = Options::in_memory();
+        let open = |options: Options| Builder::new().engine::<Engine>().open(PATH, options);
+        let db = open(options.clone())?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        db.put(&bucket, b"a", b"1", &WriteOptions::default().sync(true))?;
+        db.close()?;
+
+        // Clones of the options share the same memory.
+        let db = open(options)?;
+        let bucket = db.bucket::<Engine>("test")?;
+        assert_eq!(db.get(&bucket, b"a")?, Some(b"1".to_vec()));
+        let db = open(Options::in_memory())?;
+        match db.bucket::<Engine>("test") {
+            Err(Error::NotExist(_)) => {}
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?.env(env.clone());
+        let open = |path: &str| {
+            Builder::new()
+                .engine::<Engine>()
+                .open(path, options.clone())
+        };
+        let backups = BackupEngine::create(env.create_dir("backup")?)?;
+
+        let db = open(PATH)?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        db.put(&bucket, b"a", b"1", &WriteOptions::default())?;
+        let first = db.backup(&backups)?;
+        db.put(&bucket, b"b", b"2", &WriteOptions::default())?;
+        let second = db.backup(&backups)?;
+
+        let list = backups.list_backups()?;
+        let ids = list.iter().map(|b| b.id()).collect::<Vec<_>>();
+        assert_eq!(ids, [first, second]);
+        assert_eq!(list[0].num_files(), list[1].num_files());
+        assert!(list[0].size() < list[1].size());
+        // Only the journal is copied again in the second backup.
+        let files = env.open_dir("backup")?.open_dir("files")?;
+        assert_eq!(files.list()?.len(), list[0].num_files() + 1);
+
+        for (id, b) in [(first, None), (second, Some(b"2".to_vec()))] {
+            let path = format!("restore-{id}");
+            backups.restore_to(id, &env, &path)?;
+            let db = open(&path)?;
+            let bucket = db.bucket::<Engine>("test")?;
+            assert_eq!(db.get(&bucket, b"a")?, Some(b"1".to_vec()));
+            assert_eq!(db.get(&bucket, b"b")?, b);
+        }
+
+        match backups.restore_to(first, &env, PATH) {
+            Err(Error::Exists(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        match backups.restore_to(second + 1, &env, "restore") {
+            Err(Error::NotExist(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        for name in files.list()? {
+            files.write_file(&name, b"corrupted")?;
+        }
+        match backups.restore_to(first, &env, "restore") {
+            Err(Error::Corrupted { .. }) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+
+        // Stored files are not reused if their bytes differ, even with the
+        // same name, size and checksum.
+        let num_files = files.list()?.len();
+        let third = db.backup(&backups)?;
+        let list = backups.list_backups()?;
+        assert_eq!(files.list()?.len(), num_files + list[2].num_files());
+        backups.restore_to(third, &env, "restore")?;
+        let db = open("restore")?;
+        let bucket = db.bucket::<Engine>("test")?;
+        assert_eq!(db.get(&bucket, b"b")?, Some(b"2".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_failpoints() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?.env(env.clone());
+        let open = || {
+            Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())
+        };
+
+        // Fail before the engine is committed to the manifest.
+        failpoint::set("core::manifest::before_persist", Action::Error);
+        match open() {
+            Err(Error::Io(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        failpoint::remove("core::manifest::before_persist");
+
+        // Fail after the write is appended to the journal.
+        {
+            let db = open()?;
+            let bucket = db.create_bucket::<Engine>("test")?;
+            let mut batch = WriteBatch::new();
+            batch.bucket(&bucket).put(b"a", b"1")?;
+            failpoint::set("core::write::before_submit", Action::Error);
+            match db.write(&batch, &WriteOptions::default()) {
+                Err(Error::Io(_)) => {}
+                x => panic!("unexpected result: {x:?}"),
+            }
+            failpoint::remove("core::write::before_submit");
+            // The write is in the journal but not in the engine, so the
+            // database refuses to be used until it is reopened.
+            match db.write(&batch, &WriteOptions::default()) {
+                Err(Error::Poisoned(..)) => {}
+                x => panic!("unexpected result: {x:?}"),
+            }
+            match db.read(&bucket) {
+                Err(Error::Poisoned(..)) => {}
+                x => panic!("unexpected result: {:?}", x.map(|_| ())),
+            }
+        }
+        // The write is recovered from the journal.
+        let db = open()?;
+        let bucket = db.bucket::<Engine>("test")?;
+        assert_eq!(db.read(&bucket)?.get(b"a")?, Some(b"1".to_vec()));
+        let list = env.open_dir(PATH)?.list()?;
+        assert!(!list.iter().any(|name| TempFile::is_temp(name)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_sink() -> Result<()> {
+        #[derive(Debug, Default)]
+        struct Sink {
+            archived: Mutex<Vec<(String, Vec<u8>)>>,
+            synced: Mutex<Vec<(String, u64)>>,
+        }
+
+        impl ArchiveSink for Sink {
+            fn archive_journal(&self, dir: &Dir, name: &str) -> Result<()> {
+                let data = dir.read_file(name)?;
+                self.archived.lock().unwrap().push((name.into(), data));
+                Ok(())
+            }
+
+            fn sync_journal(&self, _: &Dir, name: &str, size: u64) -> Result<()> {
+                self.synced.lock().unwrap().push((name.into(), size));
+                Ok(())
+            }
+        }
+
+        let sink = Arc::new(Sink::default());
+        let options = Options::test()?.archive_sink(sink.clone());
+        {
+            let db = Database::open(PATH, options.clone())?;
+            db.write(&WriteBatch::new(), &WriteOptions::default())?;
+            assert!(sink.synced.lock().unwrap().is_empty());
+            db.write(&WriteBatch::new(), &WriteOptions::new().sync(true))?;
+            let synced = sink.synced.lock().unwrap();
+            assert_eq!(synced.len(), 1);
+            assert_eq!(synced[0].0, "journal-1");
+            assert!(synced[0].1 > 0);
+        }
+        assert!(sink.archived.lock().unwrap().is_empty());
+        // Recovered journals are archived before they are deleted.
+        Database::open(PATH, options)?;
+        let archived = sink.archived.lock().unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].0, "journal-1");
+        assert_eq!(archived[0].1.len() as u64, sink.synced.lock().unwrap()[0].1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trash_retention() -> Result<()> {
+        let env = Env::test()?;
+        let clock = Arc::new(MockClock::new());
+        let options = Options::test()?.env(env.clone()).clock(clock.clone());
+        let trash_files = || -> Result<Vec<String>> {
+            let mut list = env.open_dir(PATH)?.list()?;
+            list.retain(|name| name.ends_with(".trash"));
+            Ok(list)
+        };
+        let options = options.trash_retention(Duration::from_secs(3600));
+        Database::open(PATH, options.clone())?;
+        Database::open(PATH, options.clone())?;
+        let list = trash_files()?;
+        assert_eq!(list.len(), 1);
+        assert!(list[0].starts_with("journal-1."));
+        // Trash files are kept until they expire.
+        clock.advance(Duration::from_secs(1800));
+        Database::open(PATH, options.clone())?;
+        assert_eq!(trash_files()?.len(), 2);
+        // Expired trash files are purged in the background.
+        clock.advance(Duration::from_secs(1800));
+        let db = Database::open(PATH, options)?;
+        while trash_files()?.contains(&list[0]) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        drop(db);
+        assert_eq!(trash_files()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "checksums are fake under miri")]
+    fn test_goldens() -> Result<()> {
+        for files in GOLDENS {
+            let env = Env::test()?;
+            write_golden(&env, files)?;
+            let options = Options::test()?.env(env.clone());
+            for _ in 0..2 {
+                let db = Builder::new()
+                    .engine::<Engine>()
+                    .error_if_not_exist(true)
+                    .open(PATH, options.clone())?;
+                db.bucket::<Engine>("a")?;
+                match db.bucket::<Engine>("b") {
+                    Err(Error::NotExist(_)) => {}
+                    x => panic!("unexpected result: {x:?}"),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "checksums are fake under miri")]
+    fn test_unsupported_batch_version() -> Result<()> {
+        let env = Env::test()?;
+        write_golden(&env, GOLDENS.last().unwrap())?;
+        // Change the batch version in the first journal record, which is a
+        // single fragment with the LSN, the epoch and the engine id before
+        // the version.
+        patch_first_record(&env, 10, 5)?;
+        let options = Options::test()?.env(env.clone());
+        match Builder::new().engine::<Engine>().open(PATH, options) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "checksums are fake under miri")]
+    fn test_newer_epoch() -> Result<()> {
+        let env = Env::test()?;
+        write_golden(&env, GOLDENS.last().unwrap())?;
+        let options = Options::test()?.env(env.clone());
+        let open = || {
+            Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())
+        };
+        // Records from the epoch in the manifest and older ones are fine.
+        drop(open()?);
+        // Pretend that the first record is written by a database opened
+        // later, whose epoch is lost from the manifest.
+        patch_first_record(&env, 8, 100)?;
+        match open() {
+            Err(Error::Corrupted { .. }) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "checksums are fake under miri")]
+    fn test_interleaved_epochs() -> Result<()> {
+        let env = Env::test()?;
+        write_golden(&env, GOLDENS.last().unwrap())?;
+        let options = Options::test()?.env(env.clone());
+        let open = || {
+            Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())
+        };
+        // Bump the epoch in the manifest to 2.
+        drop(open()?);
+        // Pretend that the first record is written by the database opened
+        // with epoch 2, and the later ones by a stale process with epoch 1.
+        // Every epoch is within the manifest, but they go back.
+        patch_first_record(&env, 8, 2)?;
+        match open() {
+            Err(Error::Corrupted { .. }) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    /// Sets the byte at `offset` in the first record of `journal-1`.
+    ///
+    /// The record must be a single fragment in the first block.
+    fn patch_first_record(env: &Env, offset: usize, value: u8) -> Result<()> {
+        let dir = env.open_dir(PATH)?;
+        let mut data = dir.read_file("journal-1")?;
+        let size = u16::from_le_bytes([data[4], data[5]]) as usize;
+        data[offset] = value;
+        let crc = checksum_combined(&data[6..7], &data[7..7 + size]);
+        data[..4].copy_from_slice(&crc.to_le_bytes());
+        dir.write_file("journal-1", &data)?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "checksums are fake under miri")]
+    fn test_newer_format_version() -> Result<()> {
+        let env = Env::test()?;
+        write_golden(&env, GOLDENS.last().unwrap())?;
+        // Append a larger format version to the manifest.
+        let dir = env.open_dir(PATH)?;
+        let mut data = dir.read_file("MANIFEST")?;
+        data.truncate(data.len() - 4);
+        data.extend_from_slice(&[5 << 3, 100]);
+        checksum(&data).encode_to(&mut data);
+        dir.write_file("MANIFEST", &data)?;
+        let options = Options::test()?.env(env.clone());
+        match Builder::new().engine::<Engine>().open(PATH, options) {
+            Err(Error::InvalidArgument(_)) => {}
+            x => panic!("unexpected result: {x:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_write() -> Result<()> {
+        let env = Env::test()?;
+        let options = Options::test()?.env(env.clone());
+        let open = || {
+            Builder::new()
+                .engine::<Engine>()
+                .open(PATH, options.clone())
+        };
+        {
+            let db = open()?;
+            let bucket = db.create_bucket::<Engine>("test")?;
+            let mut batch = WriteBatch::new();
+            batch.bucket(&bucket).put(b"a", b"1")?.put(b"b", b"2")?;
+            db.write(&batch, &WriteOptions::default())?;
+            let reader = db.read(&bucket)?;
+            let mut batch = WriteBatch::new();
+            batch.bucket(&bucket).put(b"a", b"3")?.delete(b"b")?;
+            db.write(&batch, &WriteOptions::default())?;
+            // The reader does not see writes after it is created.
+            assert_eq!(reader.get(b"a")?, Some(b"1".to_vec()));
+            assert_eq!(reader.get(b"b")?, Some(b"2".to_vec()));
+            let reader = db.read(&bucket)?;
+            assert_eq!(reader.get(b"a")?, Some(b"3".to_vec()));
+            assert_eq!(reader.get(b"b")?, None);
+            assert_eq!(reader.get(b"c")?, None);
+        }
+        // Writes are recovered from journals every time the database is
+        // reopened.
+        for _ in 0..2 {
+            let db = open()?;
+            let bucket = db.bucket::<Engine>("test")?;
+            let reader = db.read(&bucket)?;
+            assert_eq!(reader.get(b"a")?, Some(b"3".to_vec()));
+            assert_eq!(reader.get(b"b")?, None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_shortcuts() -> Result<()> {
+        let db = test_database()?;
+        let bucket = db.create_bucket::<Engine>("test")?;
+        let options = WriteOptions::default();
+        assert_eq!(db.get(&bucket, b"a")?, None);
+        db.put(&bucket, b"a", b"1", &options)?;
+        assert_eq!(db.get(&bucket, b"a")?, Some(b"1".to_vec()));
+        db.delete(&bucket, b"a", &options)?;
+        assert_eq!(db.get(&bucket, b"a")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot() -> Result<()> {
+        let db = test_database()?;
+        let a = db.create_bucket::<Engine>("a")?;
+        let b = db.create_bucket::<Engine>("b")?;
+        let mut batch = WriteBatch::new();
+        batch.bucket(&a).put(b"1", b"a1")?;
+        batch.bucket(&b).put(b"1", b"b1")?;
+        db.write(&batch, &WriteOptions::default())?;
+
+        let snapshot = db.snapshot();
+        let mut batch = WriteBatch::new();
+        batch.bucket(&a).put(b"1", b"a2")?;
+        batch.bucket(&b).delete(b"1")?;
+        db.write(&batch, &WriteOptions::default())?;
+
+        // Readers created from the snapshot do not see later writes.
+        assert_eq!(snapshot.read(&a)?.get(b"1")?, Some(b"a1".to_vec()));
+        assert_eq!(snapshot.read(&b)?.get(b"1")?, Some(b"b1".to_vec()));
+        assert_eq!(db.read(&a)?.get(b"1")?, Some(b"a2".to_vec()));
+        assert_eq!(db.read(&b)?.get(b"1")?, None);
+        assert!(db.snapshot().lsn() > snapshot.lsn());
+        Ok(())
+    }
+
+    mod model {
+        use std::collections::BTreeMap;
+
+        use proptest::prelude::*;
+
+        use super::*;
+
+        const BUCKETS: &[&str] = &["a", "b", "c"];
+        const KEYS: u8 = 8;
+
+        #[derive(Clone, Debug)]
+        enum Op {
+            Write(Vec<(usize, u8, Option<u8>)>),
+            CreateBucket(usize),
+            DeleteBucket(usize),
+            Reopen,
+        }
+
+        fn op() -> impl Strategy<Value = Op> {
+            let bucket = 0..BUCKETS.len();
+            let record = (bucket.clone(), 0..KEYS, any::<Option<u8>>());
+            prop_oneof![
+                6 => prop::collection::vec(record, 1..4).prop_map(Op::Write),
+                2 => bucket.clone().prop_map(Op::CreateBucket),
+                1 => bucket.prop_map(Op::DeleteBucket),
+                1 => Just(Op::Reopen),
+            ]
+        }
+
+        /// The expected content of each bucket.
+        type Model = BTreeMap<&'static str, BTreeMap<u8, u8>>;
+
+        fn check(db: &Database, model: &Model) -> Result<()> {
+            for name in BUCKETS {
+                let Some(expected) = model.get(name) else {
+                    match db.bucket::<Engine>(name) {
+                        Err(Error::NotExist(_)) => {}
+                        x => panic!("unexpected result: {x:?}"),
+                    }
+                    continue;
+                };
+                let bucket = db.bucket::<Engine>(name)?;
+                let reader = db.read(&bucket)?;
+                for key in 0..KEYS {
+                    let value = expected.get(&key).map(|&v| vec![v]);
+                    assert_eq!(reader.get(&[key])?, value, "bucket {name} key {key}");
+                }
+                let values = reader.iter().collect::<Result<Vec<_>>>()?;
+                let expected = expected
+                    .iter()
+                    .map(|(&k, &v)| (vec![k], vec![v]))
+                    .collect::<Vec<_>>();
+                assert_eq!(values, expected, "bucket {name}");
+            }
+            Ok(())
+        }
+
+        fn run(ops: Vec<Op>) -> Result<()> {
+            let env = Env::test()?;
+            let options = Options::test()?.env(env);
+            let open = || {
+                Builder::new()
+                    .engine::<Engine>()
+                    .open(PATH, options.clone())
+            };
+            let mut db = open()?;
+            let mut model = Model::new();
+            for op in ops {
+                match op {
+                    Op::Write(records) => {
+                        let mut batch = WriteBatch::new();
+                        for (i, key, value) in records {
+                            let name = BUCKETS[i];
+                            let Some(expected) = model.get_mut(name) else {
+                                continue;
+                            };
+                            let bucket = db.bucket::<Engine>(name)?;
+                            let mut writer = batch.bucket(&bucket);
+                            match value {
+                                Some(v) => {
+                                    writer.put(&[key], &[v])?;
+                                    expected.insert(key, v);
+                                }
+                                None => {
+                                    writer.delete(&[key])?;
+                                    expected.remove(&key);
+                                }
+                            }
+                        }
+                        db.write(&batch, &WriteOptions::default())?;
+                    }
+                    Op::CreateBucket(i) => {
+                        let name = BUCKETS[i];
+                        match db.create_bucket::<Engine>(name) {
+                            Ok(_) => assert!(model.insert(name, BTreeMap::new()).is_none()),
+                            Err(Error::Exists(_)) => assert!(model.contains_key(name)),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    Op::DeleteBucket(i) => {
+                        let name = BUCKETS[i];
+                        match db.delete_bucket::<Engine>(name) {
+                            Ok(()) => assert!(model.remove(name).is_some()),
+                            Err(Error::NotExist(_)) => assert!(!model.contains_key(name)),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    Op::Reopen => {
+                        drop(db);
+                        db = open()?;
+                    }
+                }
+                check(&db, &model)?;
+            }
+            Ok(())
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(64))]
+
+            #[test]
+            fn test_model(ops in prop::collection::vec(op(), 1..64)) {
+                run(ops).unwrap();
+            }
+        }
+    }
 }
@@ -1,14 +1,27 @@
+#[doc(inline)]
+pub use vbase_core::backup;
+#[doc(inline)]
+pub use vbase_env as env;
+
 mod database;
 pub use database::Builder;
 pub use database::Database;
 
 mod core {
+    pub use vbase_core::EngineRef;
     pub use vbase_core::Error;
+    pub use vbase_core::Identity;
     pub use vbase_core::Result;
+    pub use vbase_core::Snapshot;
     pub use vbase_core::WriteBatch;
+    pub use vbase_core::WriteStallStats;
     pub use vbase_core::engine::Bucket;
     pub use vbase_core::engine::Engine;
+    pub use vbase_core::engine::Property;
+    pub use vbase_core::options::ArchiveSink;
     pub use vbase_core::options::Options;
+    pub use vbase_core::options::VerifyOptions;
+    pub use vbase_core::options::VerifyScope;
     pub use vbase_core::options::WriteOptions;
 }
 pub use core::*;
@@ -16,4 +29,8 @@ pub use core::*;
 pub mod tree {
     pub use vbase_tree::Bucket;
     pub use vbase_tree::Engine;
+    pub use vbase_tree::EngineHandle;
+    pub use vbase_tree::Iter;
+    pub use vbase_tree::Reader;
+    pub use vbase_tree::Writer;
 }